@@ -0,0 +1,144 @@
+use super::hex::Hex;
+use super::island::Island;
+use super::map::TileType;
+
+pub const PLAYER_GLYPH: char = '@';
+pub const SPAWN_GLYPH: char = 'S';
+/// Drawn instead of tiles the player hasn't seen yet.
+pub const UNREVEALED_GLYPH: char = '?';
+
+/// Horizontal distance between columns of tiles one unit wide, which is one text column apart.
+const COLUMN_SPACING: f32 = 0.75;
+/// Half the vertical distance between tiles one unit wide, which is one text row apart.
+const ROW_SPACING: f32 = 0.5;
+
+pub fn glyph(tile_type: TileType) -> char {
+    match tile_type {
+        TileType::DeepWater => '~',
+        TileType::Shallows => '-',
+        TileType::River => '=',
+        TileType::Ford => '#',
+        TileType::Sand => ':',
+        TileType::Grass => '.',
+        TileType::Forest => 'T',
+        TileType::Mountain => '^',
+        TileType::Exit => 'X',
+    }
+}
+
+/// What each glyph drawn by `render` stands for, on one line.
+pub fn legend() -> String {
+    let tiles = [
+        (TileType::DeepWater, "deep water"),
+        (TileType::Shallows, "shallows"),
+        (TileType::River, "river"),
+        (TileType::Ford, "ford"),
+        (TileType::Sand, "sand"),
+        (TileType::Grass, "grass"),
+        (TileType::Forest, "forest"),
+        (TileType::Mountain, "mountain"),
+        (TileType::Exit, "exit"),
+    ];
+    let markers = [
+        (PLAYER_GLYPH, "player"),
+        (SPAWN_GLYPH, "spawn"),
+        (UNREVEALED_GLYPH, "unseen"),
+    ];
+
+    tiles
+        .iter()
+        .map(|(tile_type, name)| (glyph(*tile_type), *name))
+        .chain(markers.iter().copied())
+        .map(|(glyph, name)| format!("{} {}", glyph, name))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Draws `island` as text, one character per tile, in the same layout as the game draws it: north
+/// at the top, and every other column of tiles shifted by half a tile. With `fog`, tiles the
+/// player hasn't seen are drawn as `UNREVEALED_GLYPH`.
+pub fn render(island: &Island, fog: bool) -> String {
+    let grid = island.grid();
+    let cells: Vec<((i32, i32), char)> = grid
+        .positions()
+        .map(|(x, y)| {
+            let hex = grid.hex(x, y);
+            let (px, py) = Hex::from(hex).to_point(1.0);
+            let column = (px / COLUMN_SPACING).round() as i32;
+            let row = (py / ROW_SPACING).round() as i32;
+
+            let glyph = if hex == island.player() {
+                PLAYER_GLYPH
+            } else if fog && !island.is_revealed(hex) {
+                UNREVEALED_GLYPH
+            } else if hex == island.spawn() {
+                SPAWN_GLYPH
+            } else {
+                island.tile_type(hex).map_or(' ', glyph)
+            };
+
+            ((column, row), glyph)
+        })
+        .collect();
+
+    if cells.is_empty() {
+        return String::new();
+    }
+    let columns = cells.iter().map(|((column, _), _)| *column);
+    let rows = cells.iter().map(|((_, row), _)| *row);
+    let (min_column, max_column) = (columns.clone().min().unwrap(), columns.max().unwrap());
+    let (min_row, max_row) = (rows.clone().min().unwrap(), rows.max().unwrap());
+
+    // Tiles in neighboring columns are half a row apart, so each column is drawn two characters
+    // wide to keep the rows from running together.
+    let width = 2 * (max_column - min_column) as usize + 1;
+    let height = (max_row - min_row) as usize + 1;
+    let mut lines = vec![vec![' '; width]; height];
+    for ((column, row), glyph) in cells {
+        lines[(max_row - row) as usize][2 * (column - min_column) as usize] = glyph;
+    }
+
+    lines
+        .iter()
+        .map(|line| line.iter().collect::<String>().trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::hex::HexCoord;
+    use crate::core::island::test_util::{island, strip};
+
+    #[test]
+    fn test_render_whole_island() {
+        let mut island = island();
+        assert_eq!(render(&island, false), "  X\n^\n  -\nT\n  :\n@");
+
+        island.move_to(HexCoord::new(1, 0)).unwrap();
+        assert_eq!(render(&island, false), "  X\n^\n  -\nT\n  @\nS");
+    }
+
+    #[test]
+    fn test_render_deep_water() {
+        assert_eq!(render(&strip(), false), "~\n\nX\n\n.\n\nT\n\n.\n\n.\n\n@");
+    }
+
+    #[test]
+    fn test_render_with_fog() {
+        let mut island = island();
+        assert_eq!(render(&island, true), "  ?\n?\n  ?\n?\n  ?\n@");
+
+        island.move_to(HexCoord::new(1, 0)).unwrap();
+        assert_eq!(render(&island, true), "  ?\n?\n  ?\n?\n  @\nS");
+    }
+
+    #[test]
+    fn test_legend_lists_every_glyph() {
+        let legend = legend();
+        for glyph in "~-=#:.T^X@S?".chars() {
+            assert!(legend.contains(&format!("{} ", glyph)));
+        }
+    }
+}
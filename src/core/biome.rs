@@ -0,0 +1,88 @@
+use super::map::{MapConfig, TileType};
+
+/// Moisture below which land counts as dry.
+const DRY: f64 = 0.35;
+/// Moisture above which land counts as wet.
+const WET: f64 = 0.6;
+
+/// Tile types for land between the beaches and the mountains, by elevation (below and above
+/// `forest_level`) and moisture (dry, moderate and wet).
+const LAND_BIOMES: [[TileType; 3]; 2] = [
+    [TileType::Sand, TileType::Grass, TileType::Forest],
+    [TileType::Grass, TileType::Forest, TileType::Forest],
+];
+
+/// The tile type for a tile with the given elevation and moisture, both between 0 and 1.
+pub fn biome(elevation: f64, moisture: f64, config: &MapConfig) -> TileType {
+    if elevation < config.water_level - config.shallows_depth {
+        return TileType::DeepWater;
+    }
+    if elevation < config.water_level {
+        return TileType::Shallows;
+    }
+    if elevation < config.water_level + config.beach_width {
+        return TileType::Sand;
+    }
+    if elevation >= config.mountain_level {
+        return TileType::Mountain;
+    }
+
+    let elevation_band = if elevation < config.forest_level {
+        0
+    } else {
+        1
+    };
+    let moisture_band = if moisture < DRY {
+        0
+    } else if moisture <= WET {
+        1
+    } else {
+        2
+    };
+
+    LAND_BIOMES[elevation_band][moisture_band]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_biome_by_elevation() {
+        let config = MapConfig::default();
+        let moderate = 0.5;
+
+        assert_eq!(biome(0.0, moderate, &config), TileType::DeepWater);
+        assert_eq!(
+            biome(config.water_level - 0.01, moderate, &config),
+            TileType::Shallows
+        );
+        assert_eq!(
+            biome(config.water_level + 0.01, moderate, &config),
+            TileType::Sand
+        );
+        assert_eq!(
+            biome(config.forest_level - 0.01, moderate, &config),
+            TileType::Grass
+        );
+        assert_eq!(
+            biome(config.forest_level + 0.01, moderate, &config),
+            TileType::Forest
+        );
+        assert_eq!(biome(1.0, moderate, &config), TileType::Mountain);
+    }
+
+    #[test]
+    fn test_biome_by_moisture() {
+        let config = MapConfig::default();
+        let lowland = config.forest_level - 0.05;
+
+        assert_eq!(biome(lowland, 0.1, &config), TileType::Sand);
+        assert_eq!(biome(lowland, 0.5, &config), TileType::Grass);
+        assert_eq!(biome(lowland, 0.9, &config), TileType::Forest);
+
+        // Water and mountains don't depend on moisture.
+        assert_eq!(biome(0.0, 0.9, &config), TileType::DeepWater);
+        assert_eq!(biome(1.0, 0.1, &config), TileType::Mountain);
+    }
+}
@@ -0,0 +1,667 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+/// Integer axial coordinates identifying a single tile.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct HexCoord {
+    pub q: i32,
+    pub r: i32,
+}
+
+impl HexCoord {
+    pub fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    /// Converts from the offset coordinates used to lay out the map, where every odd column is
+    /// shifted by half a tile.
+    pub fn from_offset(x: i32, y: i32) -> Self {
+        Self::new(x, y - x.div_euclid(2))
+    }
+
+    pub fn to_offset(&self) -> (i32, i32) {
+        (self.q, self.r + self.q.div_euclid(2))
+    }
+
+    pub fn distance_to(&self, other: &HexCoord) -> u32 {
+        let dq = self.q - other.q;
+        let dr = self.r - other.r;
+
+        ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as u32
+    }
+
+    pub fn neighbor(&self, direction: HexDirection) -> HexCoord {
+        *self + direction.offset()
+    }
+
+    /// The direction of `other`, if it is adjacent.
+    pub fn direction_to(&self, other: &HexCoord) -> Option<HexDirection> {
+        HexDirection::ALL
+            .iter()
+            .copied()
+            .find(|direction| self.neighbor(*direction) == *other)
+    }
+
+    /// The six adjacent hexes, clockwise from north.
+    pub fn neighbors(&self) -> impl Iterator<Item = HexCoord> {
+        let center = *self;
+        HexDirection::ALL
+            .iter()
+            .map(move |direction| center.neighbor(*direction))
+    }
+
+    /// Hexes exactly `radius` steps away, clockwise starting from the north-west corner.
+    pub fn ring(&self, radius: u32) -> impl Iterator<Item = HexCoord> {
+        let mut hexes = Vec::with_capacity(6 * radius as usize);
+
+        if radius == 0 {
+            hexes.push(*self);
+        } else {
+            let mut hex = *self + HexDirection::NorthWest.offset() * radius as i32;
+            for direction in HexDirection::NorthEast.sweep() {
+                for _ in 0..radius {
+                    hexes.push(hex);
+                    hex = hex.neighbor(direction);
+                }
+            }
+        }
+
+        hexes.into_iter()
+    }
+
+    /// Hexes within `radius` steps, ordered ring by ring outwards from this one.
+    pub fn spiral(&self, radius: u32) -> impl Iterator<Item = HexCoord> {
+        let center = *self;
+        (0..=radius).flat_map(move |ring_radius| center.ring(ring_radius))
+    }
+
+    /// Hexes along the straight line from this hex to `other`, including both ends.
+    pub fn line_to(&self, other: &HexCoord) -> Vec<HexCoord> {
+        let distance = self.distance_to(other);
+        let delta = *other - *self;
+
+        (0..=distance)
+            .map(|step| {
+                let t = if distance == 0 {
+                    0.0
+                } else {
+                    step as f32 / distance as f32
+                };
+                // Nudge the line slightly off the edges between hexes so rounding is consistent.
+                let hex = Hex::new(delta.q as f32 * t + 1e-4, delta.r as f32 * t - 3e-4);
+                *self + hex.to_hex_coord()
+            })
+            .collect()
+    }
+
+    /// Hexes within `radius` steps, ordered by `q` and then `r`.
+    pub fn range(&self, radius: u32) -> impl Iterator<Item = HexCoord> {
+        let center = *self;
+        let radius = radius as i32;
+
+        (-radius..=radius).flat_map(move |dq| {
+            let min_dr = (-radius).max(-dq - radius);
+            let max_dr = radius.min(-dq + radius);
+            (min_dr..=max_dr).map(move |dr| center + HexCoord::new(dq, dr))
+        })
+    }
+}
+
+impl Add for HexCoord {
+    type Output = HexCoord;
+
+    fn add(self, other: HexCoord) -> HexCoord {
+        HexCoord::new(self.q + other.q, self.r + other.r)
+    }
+}
+
+impl Sub for HexCoord {
+    type Output = HexCoord;
+
+    fn sub(self, other: HexCoord) -> HexCoord {
+        HexCoord::new(self.q - other.q, self.r - other.r)
+    }
+}
+
+impl Mul<i32> for HexCoord {
+    type Output = HexCoord;
+
+    fn mul(self, factor: i32) -> HexCoord {
+        HexCoord::new(self.q * factor, self.r * factor)
+    }
+}
+
+/// The six directions from a hex to its neighbors, named by where the neighbor appears on screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+    North,
+    NorthEast,
+    SouthEast,
+    South,
+    SouthWest,
+    NorthWest,
+}
+
+impl HexDirection {
+    /// All directions, clockwise from north.
+    pub const ALL: [HexDirection; 6] = [
+        HexDirection::North,
+        HexDirection::NorthEast,
+        HexDirection::SouthEast,
+        HexDirection::South,
+        HexDirection::SouthWest,
+        HexDirection::NorthWest,
+    ];
+
+    pub fn offset(&self) -> HexCoord {
+        match self {
+            HexDirection::North => HexCoord::new(0, 1),
+            HexDirection::NorthEast => HexCoord::new(1, 0),
+            HexDirection::SouthEast => HexCoord::new(1, -1),
+            HexDirection::South => HexCoord::new(0, -1),
+            HexDirection::SouthWest => HexCoord::new(-1, 0),
+            HexDirection::NorthWest => HexCoord::new(-1, 1),
+        }
+    }
+
+    /// Rotates by `steps` sixths of a turn, clockwise for positive values.
+    pub fn rotated(&self, steps: i32) -> HexDirection {
+        let index = Self::ALL.iter().position(|d| d == self).unwrap() as i32;
+        Self::ALL[(index + steps).rem_euclid(6) as usize]
+    }
+
+    pub fn clockwise(&self) -> HexDirection {
+        self.rotated(1)
+    }
+
+    pub fn counter_clockwise(&self) -> HexDirection {
+        self.rotated(-1)
+    }
+
+    pub fn opposite(&self) -> HexDirection {
+        self.rotated(3)
+    }
+
+    /// The direction closest to a screen-space vector (`x`, `y`), such as a gamepad stick
+    /// position.
+    pub fn nearest(x: f32, y: f32) -> HexDirection {
+        let angle = y.atan2(x).to_degrees();
+        let steps = ((90.0 - angle) / 60.0).round() as i32;
+        HexDirection::North.rotated(steps)
+    }
+
+    /// All six directions, clockwise starting from this one.
+    pub fn sweep(&self) -> impl Iterator<Item = HexDirection> {
+        let start = *self;
+        (0..6).map(move |steps| start.rotated(steps))
+    }
+}
+
+impl fmt::Display for HexDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let abbreviation = match self {
+            HexDirection::North => "n",
+            HexDirection::NorthEast => "ne",
+            HexDirection::SouthEast => "se",
+            HexDirection::South => "s",
+            HexDirection::SouthWest => "sw",
+            HexDirection::NorthWest => "nw",
+        };
+        write!(f, "{}", abbreviation)
+    }
+}
+
+impl FromStr for HexDirection {
+    type Err = String;
+
+    /// Parses a compass abbreviation such as `ne`, as written by `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HexDirection::ALL
+            .iter()
+            .copied()
+            .find(|direction| direction.to_string() == s.to_lowercase())
+            .ok_or_else(|| format!("unknown direction {}", s))
+    }
+}
+
+/// Finds the cheapest path from `start` to `goal` using A*, where `cost` gives the cost of stepping
+/// onto a hex (at least 1), or `None` if it can't be entered. The path includes both ends.
+pub fn find_path(
+    start: HexCoord,
+    goal: HexCoord,
+    cost: impl Fn(HexCoord) -> Option<u32>,
+) -> Option<Vec<HexCoord>> {
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut best_costs = HashMap::new();
+
+    best_costs.insert(start, 0);
+    open.push(Reverse((start.distance_to(&goal), 0, start)));
+
+    while let Some(Reverse((_, cost_so_far, hex))) = open.pop() {
+        if hex == goal {
+            let mut path = vec![hex];
+            while let Some(previous) = came_from.get(path.last().unwrap()) {
+                path.push(*previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        if cost_so_far > best_costs[&hex] {
+            continue;
+        }
+
+        for neighbor in hex.neighbors() {
+            let step_cost = match cost(neighbor) {
+                Some(step_cost) => step_cost,
+                None => continue,
+            };
+            let new_cost = cost_so_far + step_cost;
+
+            if best_costs.get(&neighbor).map_or(true, |&c| new_cost < c) {
+                best_costs.insert(neighbor, new_cost);
+                came_from.insert(neighbor, hex);
+                open.push(Reverse((
+                    new_cost + neighbor.distance_to(&goal),
+                    new_cost,
+                    neighbor,
+                )));
+            }
+        }
+    }
+
+    None
+}
+
+/// Hexes within `radius` of `center` whose line of sight from `center` does not pass through a
+/// hex that blocks sight. Hexes that block sight are themselves visible.
+pub fn visible_from(
+    center: HexCoord,
+    radius: u32,
+    blocks_sight: impl Fn(HexCoord) -> bool,
+) -> impl Iterator<Item = HexCoord> {
+    center.spiral(radius).filter(move |target| {
+        let line = center.line_to(target);
+        line.iter()
+            .skip(1)
+            .take(line.len().saturating_sub(2))
+            .all(|hex| !blocks_sight(*hex))
+    })
+}
+
+/// Fractional axial coordinates, used for converting between pixels and tiles.
+#[derive(Copy, Clone)]
+pub struct Hex {
+    pub q: f32,
+    pub r: f32,
+}
+
+impl Hex {
+    pub fn new(q: f32, r: f32) -> Self {
+        Self { q, r }
+    }
+
+    pub fn to_cube_coords(&self) -> Cube {
+        let x = self.q;
+        let z = self.r;
+        let y = -x - z;
+
+        Cube::new(x, y, z)
+    }
+
+    pub fn rounded(&self) -> Hex {
+        self.to_cube_coords().rounded().to_axial_coords()
+    }
+
+    /// Rounds to the coordinates of the tile containing this hex.
+    pub fn to_hex_coord(&self) -> HexCoord {
+        let rounded = self.rounded();
+        HexCoord::new(rounded.q as i32, rounded.r as i32)
+    }
+
+    /// The hex at (`x`, `y`) when tiles are `tile_size` wide, with y pointing north.
+    pub fn from_point(x: f32, y: f32, tile_size: f32) -> Self {
+        let root3 = 3.0_f32.sqrt();
+        let q = (2.0 / 3.0 * x) / size_w(tile_size);
+        let r = (y / size_h(tile_size) - root3 / 2. * q) / root3;
+
+        Self::new(q, r)
+    }
+
+    /// Position of the center of the hex when tiles are `tile_size` wide, with y pointing north.
+    pub fn to_point(&self, tile_size: f32) -> (f32, f32) {
+        let root3 = 3.0_f32.sqrt();
+        let x = size_w(tile_size) * (3.0 / 2.0 * self.q);
+        let y = size_h(tile_size) * (root3 / 2.0 * self.q + root3 * self.r);
+        (x, y)
+    }
+}
+
+fn size_w(tile_size: f32) -> f32 {
+    tile_size / 2.0
+}
+
+fn size_h(tile_size: f32) -> f32 {
+    tile_size / 3.0_f32.sqrt()
+}
+
+impl From<HexCoord> for Hex {
+    fn from(coord: HexCoord) -> Self {
+        Self::new(coord.q as f32, coord.r as f32)
+    }
+}
+
+pub struct Cube {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+impl Cube {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn to_axial_coords(&self) -> Hex {
+        let q = self.x;
+        let r = self.z;
+
+        Hex::new(q, r)
+    }
+
+    pub fn rounded(&self) -> Cube {
+        let mut rx = self.x.round();
+        let mut ry = self.y.round();
+        let mut rz = self.z.round();
+
+        let x_diff = (rx - self.x).abs();
+        let y_diff = (ry - self.y).abs();
+        let z_diff = (rz - self.z).abs();
+
+        if x_diff > y_diff && x_diff > z_diff {
+            rx = -ry - rz;
+        } else if y_diff > z_diff {
+            ry = -rx - rz;
+        } else {
+            rz = -rx - ry;
+        }
+
+        Cube::new(rx, ry, rz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_coord_rounding_negative() {
+        assert_eq!(Hex::new(-0.6, 0.0).to_hex_coord(), HexCoord::new(-1, 0));
+        assert_eq!(Hex::new(0.4, 0.0).to_hex_coord(), HexCoord::new(0, 0));
+        assert_eq!(Hex::new(-0.2, -0.2).to_hex_coord(), HexCoord::new(0, 0));
+        assert_eq!(Hex::new(-1.2, -0.9).to_hex_coord(), HexCoord::new(-1, -1));
+        assert_ne!(
+            Hex::new(-0.6, 0.0).to_hex_coord(),
+            Hex::new(0.4, 0.0).to_hex_coord()
+        );
+    }
+
+    #[test]
+    fn test_hex_coord_offset_round_trip() {
+        assert_eq!(HexCoord::from_offset(-1, 0), HexCoord::new(-1, 1));
+        assert_eq!(HexCoord::from_offset(-2, 0), HexCoord::new(-2, 1));
+        assert_eq!(HexCoord::from_offset(3, 4), HexCoord::new(3, 3));
+
+        for x in -5..=5 {
+            for y in -5..=5 {
+                assert_eq!(HexCoord::from_offset(x, y).to_offset(), (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_coord_distance() {
+        let origin = HexCoord::new(0, 0);
+        assert_eq!(origin.distance_to(&HexCoord::new(-1, 0)), 1);
+        assert_eq!(origin.distance_to(&HexCoord::new(-1, 1)), 1);
+        assert_eq!(origin.distance_to(&HexCoord::new(-2, -1)), 3);
+        assert_eq!(HexCoord::new(-3, 2).distance_to(&HexCoord::new(2, -3)), 5);
+        assert_eq!(HexCoord::new(-3, 2).distance_to(&HexCoord::new(4, 1)), 7);
+    }
+
+    #[test]
+    fn test_hex_direction_rotation() {
+        assert_eq!(HexDirection::North.clockwise(), HexDirection::NorthEast);
+        assert_eq!(
+            HexDirection::North.counter_clockwise(),
+            HexDirection::NorthWest
+        );
+        assert_eq!(HexDirection::SouthWest.rotated(14), HexDirection::North);
+        assert_eq!(HexDirection::SouthWest.rotated(-7), HexDirection::South);
+
+        for direction in HexDirection::ALL.iter() {
+            assert_eq!(direction.opposite().opposite(), *direction);
+            assert_eq!(
+                direction.offset() + direction.opposite().offset(),
+                HexCoord::new(0, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_hex_coord_neighbors() {
+        let center = HexCoord::new(-2, 3);
+        let neighbors: Vec<HexCoord> = center.neighbors().collect();
+
+        assert_eq!(neighbors.len(), 6);
+        assert_eq!(neighbors[0], HexCoord::new(-2, 4));
+        for neighbor in neighbors {
+            assert_eq!(center.distance_to(&neighbor), 1);
+        }
+    }
+
+    #[test]
+    fn test_hex_coord_ring() {
+        let center = HexCoord::new(1, -1);
+        assert_eq!(center.ring(0).collect::<Vec<_>>(), vec![center]);
+
+        for radius in 1..5 {
+            let ring: Vec<HexCoord> = center.ring(radius).collect();
+            assert_eq!(ring.len(), 6 * radius as usize);
+            for (i, hex) in ring.iter().enumerate() {
+                assert_eq!(center.distance_to(hex), radius);
+                assert_eq!(hex.distance_to(&ring[(i + 1) % ring.len()]), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_coord_spiral_and_range() {
+        let center = HexCoord::new(-3, -4);
+
+        for radius in 0..5 {
+            let mut spiral: Vec<HexCoord> = center.spiral(radius).collect();
+            let range: Vec<HexCoord> = center.range(radius).collect();
+            let expected_len = (3 * radius * (radius + 1) + 1) as usize;
+
+            assert_eq!(spiral[0], center);
+            assert_eq!(spiral.len(), expected_len);
+            assert_eq!(range.len(), expected_len);
+            assert!(range.iter().all(|hex| center.distance_to(hex) <= radius));
+
+            spiral.sort();
+            assert_eq!(spiral, range);
+        }
+    }
+
+    #[test]
+    fn test_hex_coord_line_to() {
+        let start = HexCoord::new(-2, 1);
+        assert_eq!(start.line_to(&start), vec![start]);
+
+        let straight: Vec<HexCoord> = (0..4)
+            .map(|i| start + HexDirection::SouthEast.offset() * i)
+            .collect();
+        assert_eq!(start.line_to(&straight[3]), straight);
+
+        for end in start.ring(5) {
+            let line = start.line_to(&end);
+            assert_eq!(line.len(), 6);
+            assert_eq!(line[0], start);
+            assert_eq!(line[5], end);
+            for pair in line.windows(2) {
+                assert_eq!(pair[0].distance_to(&pair[1]), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_path_straight() {
+        let start = HexCoord::new(0, 0);
+        let goal = HexCoord::new(3, -3);
+        let path = find_path(start, goal, |_| Some(1)).unwrap();
+
+        assert_eq!(path, start.line_to(&goal));
+        assert_eq!(find_path(start, start, |_| Some(1)).unwrap(), vec![start]);
+    }
+
+    #[test]
+    fn test_find_path_around_wall() {
+        let start = HexCoord::new(0, 0);
+        let goal = HexCoord::new(0, 4);
+        let wall: Vec<HexCoord> = HexCoord::new(0, 2).spiral(1).collect();
+        let cost = |hex: HexCoord| {
+            if hex.distance_to(&start) > 6 || wall.contains(&hex) {
+                None
+            } else {
+                Some(1)
+            }
+        };
+        let path = find_path(start, goal, cost).unwrap();
+
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert_eq!(path.len(), 7);
+        for pair in path.windows(2) {
+            assert_eq!(pair[0].distance_to(&pair[1]), 1);
+            assert!(!wall.contains(&pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_find_path_prefers_cheap_tiles() {
+        let start = HexCoord::new(0, 0);
+        let goal = HexCoord::new(2, 0);
+        let swamp = HexCoord::new(1, 0);
+        let cost = |hex: HexCoord| {
+            if hex.distance_to(&start) > 3 {
+                None
+            } else if hex == swamp {
+                Some(5)
+            } else {
+                Some(1)
+            }
+        };
+        let path = find_path(start, goal, cost).unwrap();
+
+        assert_eq!(path.len(), 4);
+        assert!(!path.contains(&swamp));
+    }
+
+    #[test]
+    fn test_find_path_unreachable() {
+        let start = HexCoord::new(0, 0);
+        let goal = HexCoord::new(2, 2);
+        let cost = |hex: HexCoord| {
+            if hex.distance_to(&goal) <= 1 || hex.distance_to(&start) > 8 {
+                None
+            } else {
+                Some(1)
+            }
+        };
+
+        assert!(find_path(start, goal, cost).is_none());
+    }
+
+    #[test]
+    fn test_hex_coord_hash_and_order() {
+        use std::collections::HashSet;
+
+        let coords: HashSet<HexCoord> = vec![
+            HexCoord::new(-1, 0),
+            HexCoord::new(0, 0),
+            HexCoord::new(0, -1),
+            HexCoord::new(-1, 0),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(coords.len(), 3);
+
+        let mut sorted: Vec<HexCoord> = coords.into_iter().collect();
+        sorted.sort();
+        assert_eq!(
+            sorted,
+            vec![
+                HexCoord::new(-1, 0),
+                HexCoord::new(0, -1),
+                HexCoord::new(0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hex_direction_nearest() {
+        assert_eq!(HexDirection::nearest(0.0, 1.0), HexDirection::North);
+        assert_eq!(HexDirection::nearest(0.9, 0.4), HexDirection::NorthEast);
+        assert_eq!(HexDirection::nearest(0.9, -0.4), HexDirection::SouthEast);
+        assert_eq!(HexDirection::nearest(0.1, -1.0), HexDirection::South);
+        assert_eq!(HexDirection::nearest(-1.0, -0.2), HexDirection::SouthWest);
+        assert_eq!(HexDirection::nearest(-1.0, 0.2), HexDirection::NorthWest);
+    }
+    #[test]
+    fn test_hex_direction_parse_and_direction_to() {
+        let origin = HexCoord::new(2, -1);
+        for direction in HexDirection::ALL.iter() {
+            assert_eq!(direction.to_string().parse(), Ok(*direction));
+            assert_eq!(
+                origin.direction_to(&origin.neighbor(*direction)),
+                Some(*direction)
+            );
+        }
+
+        assert_eq!("SW".parse(), Ok(HexDirection::SouthWest));
+        assert!("up".parse::<HexDirection>().is_err());
+        assert_eq!(origin.direction_to(&HexCoord::new(4, -1)), None);
+        assert_eq!(origin.direction_to(&origin), None);
+    }
+
+    #[test]
+    fn test_visible_from_blocked_by_forest() {
+        let center = HexCoord::new(0, 0);
+        let forest = center.neighbor(HexDirection::North);
+        let visible: Vec<HexCoord> = visible_from(center, 3, |hex| hex == forest).collect();
+
+        assert!(visible.contains(&center));
+        assert!(visible.contains(&forest));
+        assert!(!visible.contains(&forest.neighbor(HexDirection::North)));
+        assert!(!visible.contains(&HexCoord::new(0, 3)));
+        assert!(visible.contains(&center.neighbor(HexDirection::South)));
+        assert!(visible.contains(&HexCoord::new(3, -3)));
+        for hidden in center.spiral(3).filter(|hex| !visible.contains(hex)) {
+            assert_eq!(hidden.distance_to(&center), hidden.distance_to(&forest) + 1);
+        }
+    }
+
+    #[test]
+    fn test_visible_from_open_ground() {
+        let center = HexCoord::new(4, -2);
+        let visible: Vec<HexCoord> = visible_from(center, 2, |_| false).collect();
+
+        assert_eq!(visible, center.spiral(2).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,189 @@
+use rand::prelude::*;
+
+use super::map::{MapConfig, TileGrid, TileType};
+
+/// Rivers shorter than this are left out, so that springs right next to each other don't turn
+/// into puddles.
+const MIN_RIVER_LENGTH: usize = 3;
+
+/// Runs rivers downhill from springs on high ground, following `elevation`, until they reach the
+/// sea or another river. A river that gets stuck in a basin fills it with a lake instead.
+pub fn carve_rivers(
+    grid: &mut TileGrid,
+    elevation: impl Fn(u32, u32) -> f64,
+    config: &MapConfig,
+    rng: &mut impl Rng,
+) {
+    let mut sources: Vec<(u32, u32)> = grid
+        .positions()
+        .filter(|&(x, y)| elevation(x, y) >= config.river_source_level)
+        .collect();
+    sources.shuffle(rng);
+
+    let mut rivers = 0;
+    for source in sources {
+        if rivers == config.river_count {
+            break;
+        }
+        // Keeps springs off the coast and away from rivers already carved.
+        if is_water(grid.get(source.0, source.1))
+            || grid
+                .neighbors(source.0, source.1)
+                .any(|(x, y)| is_water(grid.get(x, y)))
+        {
+            continue;
+        }
+
+        let (course, basin) = trace_river(grid, &elevation, source);
+        if course.len() < MIN_RIVER_LENGTH {
+            continue;
+        }
+
+        for (i, &(x, y)) in course.iter().enumerate() {
+            let is_ford = config.ford_spacing > 0 && (i as u32 + 1) % config.ford_spacing == 0;
+            let tile_type = if is_ford {
+                TileType::Ford
+            } else {
+                TileType::River
+            };
+            grid.set(x, y, tile_type);
+        }
+        if basin {
+            let (x, y) = *course.last().unwrap();
+            let lake: Vec<(u32, u32)> = grid.neighbors(x, y).collect();
+            grid.set(x, y, TileType::DeepWater);
+            for (x, y) in lake {
+                if grid.get(x, y) != TileType::Ford {
+                    grid.set(x, y, TileType::DeepWater);
+                }
+            }
+        }
+
+        rivers += 1;
+    }
+}
+
+fn is_water(tile_type: TileType) -> bool {
+    matches!(
+        tile_type,
+        TileType::DeepWater | TileType::Shallows | TileType::River | TileType::Ford
+    )
+}
+
+/// Follows the steepest way down from `source`. Returns the tiles on the way, and whether the
+/// river ended in a basin rather than flowing into water.
+fn trace_river(
+    grid: &TileGrid,
+    elevation: impl Fn(u32, u32) -> f64,
+    source: (u32, u32),
+) -> (Vec<(u32, u32)>, bool) {
+    let mut course = vec![source];
+    let mut current = source;
+
+    loop {
+        if grid
+            .neighbors(current.0, current.1)
+            .any(|(x, y)| is_water(grid.get(x, y)))
+        {
+            return (course, false);
+        }
+
+        let lowest = grid
+            .neighbors(current.0, current.1)
+            .filter(|neighbor| !course.contains(neighbor))
+            .min_by(|&(ax, ay), &(bx, by)| {
+                elevation(ax, ay)
+                    .partial_cmp(&elevation(bx, by))
+                    .expect("Elevations should never be NaN.")
+            });
+
+        match lowest {
+            Some(next) if elevation(next.0, next.1) < elevation(current.0, current.1) => {
+                course.push(next);
+                current = next;
+            }
+            _ => return (course, true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    fn river_config(ford_spacing: u32) -> MapConfig {
+        MapConfig {
+            river_count: 1,
+            river_source_level: 0.9,
+            ford_spacing,
+            ..Default::default()
+        }
+    }
+
+    /// Land sloping down to the sea along the left edge.
+    fn coast() -> TileGrid {
+        let tiles = (0..3)
+            .flat_map(|_| {
+                (0..8).map(|x| {
+                    if x == 0 {
+                        TileType::DeepWater
+                    } else {
+                        TileType::Grass
+                    }
+                })
+            })
+            .collect();
+        TileGrid::new(8, 3, tiles)
+    }
+
+    fn count(grid: &TileGrid, tile_type: TileType) -> usize {
+        grid.positions()
+            .filter(|&(x, y)| grid.get(x, y) == tile_type)
+            .count()
+    }
+
+    #[test]
+    fn test_river_flows_downhill_to_the_sea() {
+        let mut grid = coast();
+        let mut rng = StdRng::seed_from_u64(83721);
+        carve_rivers(&mut grid, |x, _| x as f64 / 7.0, &river_config(0), &mut rng);
+
+        // One river tile per column from the spring down to the coast.
+        assert_eq!(count(&grid, TileType::River), 7);
+        assert!((0..3).any(|y| grid.get(1, y) == TileType::River));
+        assert_eq!(count(&grid, TileType::DeepWater), 3);
+    }
+
+    #[test]
+    fn test_river_has_fords() {
+        let mut grid = coast();
+        let mut rng = StdRng::seed_from_u64(83721);
+        carve_rivers(&mut grid, |x, _| x as f64 / 7.0, &river_config(3), &mut rng);
+
+        assert_eq!(count(&grid, TileType::Ford), 2);
+        assert_eq!(count(&grid, TileType::River), 5);
+        assert!(TileType::Ford.is_walkable());
+        assert!(!TileType::River.is_walkable());
+    }
+
+    #[test]
+    fn test_river_fills_basin_with_lake() {
+        let mut grid = TileGrid::new(7, 7, vec![TileType::Grass; 49]);
+        let center = grid.hex(3, 3);
+        let hexes = grid.clone();
+        let elevation = |x, y| hexes.hex(x, y).distance_to(&center) as f64 / 10.0;
+        let config = MapConfig {
+            river_source_level: 0.45,
+            ..river_config(0)
+        };
+        let mut rng = StdRng::seed_from_u64(83721);
+        carve_rivers(&mut grid, elevation, &config, &mut rng);
+
+        assert_eq!(grid.get(3, 3), TileType::DeepWater);
+        assert!(grid
+            .neighbors(3, 3)
+            .all(|(x, y)| grid.get(x, y) == TileType::DeepWater));
+        assert!(count(&grid, TileType::River) >= MIN_RIVER_LENGTH - 1);
+    }
+}
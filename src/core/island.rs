@@ -0,0 +1,335 @@
+use rand::prelude::*;
+use std::collections::HashSet;
+use std::fmt;
+
+use super::hex::{find_path, visible_from, HexCoord};
+use super::map::{pick_exit, Difficulty, GeneratedIsland, TileGrid, TileType};
+
+/// An island being played: its terrain, where the player and the exit are, and which tiles the
+/// player has seen.
+#[derive(Debug, Clone)]
+pub struct Island {
+    grid: TileGrid,
+    /// Where the player started, or where they resumed from a save.
+    spawn: HexCoord,
+    player: HexCoord,
+    exit: HexCoord,
+    /// Walking distance from the spawn to the exit.
+    exit_distance: u32,
+    sight_radius: u32,
+    revealed: HashSet<HexCoord>,
+}
+
+/// What happened when the player stepped onto a tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveOutcome {
+    /// Movement cost of the tile stepped onto.
+    pub cost: u32,
+    /// Tiles seen for the first time from the new position.
+    pub revealed: Vec<HexCoord>,
+    pub reached_exit: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MoveError {
+    /// The player has already reached the exit.
+    Finished,
+    NotAdjacent,
+    OffMap,
+    Blocked(TileType),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Finished => write!(f, "the player has already reached the exit"),
+            MoveError::NotAdjacent => write!(f, "the player can only step onto adjacent tiles"),
+            MoveError::OffMap => write!(f, "there is no tile there"),
+            MoveError::Blocked(tile_type) => write!(f, "{:?} can't be walked on", tile_type),
+        }
+    }
+}
+
+impl Island {
+    /// An island with the player at `player`, having seen only what is visible from there.
+    pub fn new(
+        grid: TileGrid,
+        player: HexCoord,
+        exit: HexCoord,
+        exit_distance: u32,
+        sight_radius: u32,
+    ) -> Self {
+        let mut island = Island {
+            grid,
+            spawn: player,
+            player,
+            exit,
+            exit_distance,
+            sight_radius,
+            revealed: HashSet::new(),
+        };
+        island.reveal_visible();
+        island
+    }
+
+    /// Places the spawn on a random tile of a generated island's landmass, and the exit as far
+    /// from it as `difficulty` asks for.
+    pub fn place(
+        (grid, landmass): GeneratedIsland,
+        difficulty: Difficulty,
+        sight_radius: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let spawn = landmass[rng.gen_range(0..landmass.len())];
+        let (exit, exit_distance) = pick_exit(&grid, spawn, difficulty, rng)
+            .expect("The landmass should have more than one tile.");
+        let player = grid.hex(spawn.0, spawn.1);
+        let exit = grid.hex(exit.0, exit.1);
+
+        Island::new(grid, player, exit, exit_distance, sight_radius)
+    }
+
+    pub fn grid(&self) -> &TileGrid {
+        &self.grid
+    }
+
+    pub fn spawn(&self) -> HexCoord {
+        self.spawn
+    }
+
+    pub fn player(&self) -> HexCoord {
+        self.player
+    }
+
+    pub fn exit(&self) -> HexCoord {
+        self.exit
+    }
+
+    pub fn exit_distance(&self) -> u32 {
+        self.exit_distance
+    }
+
+    pub fn revealed(&self) -> &HashSet<HexCoord> {
+        &self.revealed
+    }
+
+    pub fn is_revealed(&self, hex: HexCoord) -> bool {
+        self.revealed.contains(&hex)
+    }
+
+    pub fn has_escaped(&self) -> bool {
+        self.player == self.exit
+    }
+
+    /// The type of the tile at `hex`, with the exit as its own type.
+    pub fn tile_type(&self, hex: HexCoord) -> Option<TileType> {
+        if hex == self.exit {
+            return Some(TileType::Exit);
+        }
+        self.grid.position(hex).map(|(x, y)| self.grid.get(x, y))
+    }
+
+    pub fn movement_cost(&self, hex: HexCoord) -> Option<u32> {
+        self.tile_type(hex)
+            .and_then(|tile_type| tile_type.movement_cost())
+    }
+
+    pub fn is_walkable(&self, hex: HexCoord) -> bool {
+        self.movement_cost(hex).is_some()
+    }
+
+    /// Number of walkable tiles the player has seen.
+    pub fn explored(&self) -> usize {
+        self.revealed
+            .iter()
+            .filter(|hex| self.is_walkable(**hex))
+            .count()
+    }
+
+    /// Marks the tiles at `hexes` as seen, e.g. when resuming a saved island. Returns the ones that
+    /// are on the map and weren't seen before.
+    pub fn reveal(&mut self, hexes: impl IntoIterator<Item = HexCoord>) -> Vec<HexCoord> {
+        let mut newly_revealed = Vec::new();
+        for hex in hexes {
+            if self.grid.position(hex).is_some() && self.revealed.insert(hex) {
+                newly_revealed.push(hex);
+            }
+        }
+        newly_revealed
+    }
+
+    /// Marks every tile within sight of the player as seen, and returns the ones seen for the
+    /// first time.
+    pub fn reveal_visible(&mut self) -> Vec<HexCoord> {
+        let visible: Vec<HexCoord> = visible_from(self.player, self.sight_radius, |hex| {
+            self.tile_type(hex)
+                .map_or(false, |tile_type| tile_type.blocks_sight())
+        })
+        .collect();
+        self.reveal(visible)
+    }
+
+    /// The cheapest way to `goal` over tiles the player has already seen, not including the
+    /// player's own tile.
+    pub fn path_to(&self, goal: HexCoord) -> Option<Vec<HexCoord>> {
+        if goal == self.player {
+            return None;
+        }
+        let path = find_path(self.player, goal, |hex| {
+            if self.is_revealed(hex) {
+                self.movement_cost(hex)
+            } else {
+                None
+            }
+        })?;
+        Some(path.into_iter().skip(1).collect())
+    }
+
+    /// Steps the player onto the adjacent tile at `hex`.
+    pub fn move_to(&mut self, hex: HexCoord) -> Result<MoveOutcome, MoveError> {
+        if self.has_escaped() {
+            return Err(MoveError::Finished);
+        }
+        if self.player.distance_to(&hex) != 1 {
+            return Err(MoveError::NotAdjacent);
+        }
+        let tile_type = self.tile_type(hex).ok_or(MoveError::OffMap)?;
+        let cost = tile_type
+            .movement_cost()
+            .ok_or(MoveError::Blocked(tile_type))?;
+
+        self.player = hex;
+        Ok(MoveOutcome {
+            cost,
+            revealed: self.reveal_visible(),
+            reached_exit: self.has_escaped(),
+        })
+    }
+}
+
+/// Small hand-built islands for tests, here and, through the `test-util` feature, in the game.
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util {
+    use super::Island;
+    use crate::core::hex::HexCoord;
+    use crate::core::map::{TileGrid, TileType};
+
+    /// Two columns of three tiles, with the player at the south-west corner and the exit at the
+    /// north-east one, three steps away across the shallows.
+    pub fn island() -> Island {
+        let grid = TileGrid::new(
+            2,
+            3,
+            vec![
+                TileType::Grass,
+                TileType::Sand,
+                TileType::Forest,
+                TileType::Shallows,
+                TileType::Mountain,
+                TileType::Grass,
+            ],
+        );
+
+        Island::new(grid, HexCoord::new(0, 0), HexCoord::from_offset(1, 2), 3, 0)
+    }
+
+    /// A strip of grass with a forest in the middle and water at the north end.
+    pub fn strip() -> Island {
+        let mut tiles = vec![TileType::Grass; 7];
+        tiles[3] = TileType::Forest;
+        tiles[6] = TileType::DeepWater;
+        let grid = TileGrid::new(1, 7, tiles);
+
+        Island::new(grid, HexCoord::new(0, 0), HexCoord::new(0, 5), 5, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::strip;
+    use super::*;
+    use crate::core::hex::HexDirection;
+    use crate::core::map::{generate_island, MapConfig};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_new_island_reveals_around_player() {
+        let island = strip();
+
+        assert!(island.is_revealed(HexCoord::new(0, 2)));
+        assert!(!island.is_revealed(HexCoord::new(0, 3)));
+        assert_eq!(island.explored(), 3);
+        assert_eq!(island.tile_type(HexCoord::new(0, 5)), Some(TileType::Exit));
+    }
+
+    #[test]
+    fn test_move_to_reveals_and_reaches_exit() {
+        let mut island = strip();
+        for r in 1..=3 {
+            let outcome = island.move_to(HexCoord::new(0, r)).unwrap();
+            assert!(!outcome.reached_exit);
+            if r == 3 {
+                assert_eq!(outcome.cost, 2);
+            }
+        }
+        assert!(island.is_revealed(HexCoord::new(0, 5)));
+
+        island.move_to(HexCoord::new(0, 4)).unwrap();
+        let outcome = island.move_to(HexCoord::new(0, 5)).unwrap();
+        assert!(outcome.reached_exit);
+        assert!(island.has_escaped());
+        assert_eq!(
+            island.move_to(HexCoord::new(0, 4)),
+            Err(MoveError::Finished)
+        );
+    }
+
+    #[test]
+    fn test_move_to_rejects_invalid_steps() {
+        let mut island = strip();
+
+        assert_eq!(
+            island.move_to(HexCoord::new(0, 2)),
+            Err(MoveError::NotAdjacent)
+        );
+        assert_eq!(
+            island.move_to(HexCoord::new(0, 0).neighbor(HexDirection::South)),
+            Err(MoveError::OffMap)
+        );
+
+        island.exit = HexCoord::new(0, 9);
+        for r in 1..=5 {
+            island.move_to(HexCoord::new(0, r)).unwrap();
+        }
+        assert_eq!(
+            island.move_to(HexCoord::new(0, 6)),
+            Err(MoveError::Blocked(TileType::DeepWater))
+        );
+        assert_eq!(island.player(), HexCoord::new(0, 5));
+    }
+
+    #[test]
+    fn test_path_to_only_uses_revealed_tiles() {
+        let island = strip();
+
+        assert_eq!(
+            island.path_to(HexCoord::new(0, 2)),
+            Some(vec![HexCoord::new(0, 1), HexCoord::new(0, 2)])
+        );
+        assert_eq!(island.path_to(HexCoord::new(0, 4)), None);
+        assert_eq!(island.path_to(island.player()), None);
+    }
+
+    #[test]
+    fn test_place_generated_island() {
+        let config = MapConfig::default();
+        let mut rng = StdRng::seed_from_u64(83721);
+        let generated = generate_island(&config, &mut rng, |_| {}).unwrap();
+        let island = Island::place(generated, Difficulty::Hard, 3, &mut rng);
+
+        assert!(island.movement_cost(island.player()).is_some());
+        assert_ne!(island.player(), island.exit());
+        assert!(island.exit_distance() > 0);
+        assert!(island.is_revealed(island.player()));
+    }
+}
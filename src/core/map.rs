@@ -0,0 +1,914 @@
+use noise::{NoiseFn, OpenSimplex, Seedable};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use super::biome::biome;
+use super::hex::HexCoord;
+use super::hydrology::carve_rivers;
+use super::shape::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TileType {
+    DeepWater,
+    Shallows,
+    River,
+    /// A shallow crossing of a river.
+    Ford,
+    Sand,
+    Grass,
+    Forest,
+    Mountain,
+    Exit,
+}
+
+impl TileType {
+    /// How long stepping onto a tile of this type takes, in steps on grass, or `None` if it can't
+    /// be entered at all.
+    pub fn movement_cost(&self) -> Option<u32> {
+        match self {
+            TileType::DeepWater | TileType::River | TileType::Mountain => None,
+            TileType::Grass | TileType::Exit => Some(1),
+            TileType::Sand | TileType::Forest => Some(2),
+            TileType::Shallows | TileType::Ford => Some(3),
+        }
+    }
+
+    pub fn is_walkable(&self) -> bool {
+        self.movement_cost().is_some()
+    }
+
+    pub fn blocks_sight(&self) -> bool {
+        matches!(self, TileType::Forest | TileType::Mountain)
+    }
+}
+
+/// Parameters for generating islands, read from the `map` section of the settings file and
+/// overridable from the command line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Height below which tiles are water.
+    pub water_level: f64,
+    /// How far below `water_level` the water is still shallow enough to wade through.
+    pub shallows_depth: f64,
+    /// How far above `water_level` the land is beach.
+    pub beach_width: f64,
+    /// Height above which land is forest, unless it is dry.
+    pub forest_level: f64,
+    /// Height above which tiles are impassable mountains.
+    pub mountain_level: f64,
+    /// Fewest walkable tiles the largest landmass needs for an island to be accepted.
+    pub walkable_tile_threshold: usize,
+    /// Frequency of the first noise octave.
+    pub octave_scale: f64,
+    pub octave_count: usize,
+    /// How much each noise octave's amplitude is scaled from the previous one.
+    pub octave_persistence: f64,
+    /// Frequency of the first octave of the moisture noise, which decides between sand, grass and
+    /// forest.
+    pub moisture_scale: f64,
+    pub shape: IslandShape,
+    /// Most rivers to run down from the high ground.
+    pub river_count: u32,
+    /// Height above which rivers can have their springs.
+    pub river_source_level: f64,
+    /// Every this many river tiles, one is a ford that can be crossed. 0 makes rivers impassable.
+    pub ford_spacing: u32,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        MapConfig {
+            width: 40,
+            height: 40,
+            water_level: 0.27,
+            shallows_depth: 0.04,
+            beach_width: 0.02,
+            forest_level: 0.5,
+            mountain_level: 0.64,
+            walkable_tile_threshold: 250,
+            octave_scale: 0.025,
+            octave_count: 8,
+            octave_persistence: 0.015,
+            moisture_scale: 0.06,
+            shape: IslandShape::default(),
+            river_count: 3,
+            river_source_level: 0.5,
+            ford_spacing: 5,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MapConfigError {
+    TooSmall { width: u32, height: u32 },
+    LevelOutOfRange { name: &'static str, value: f64 },
+    InvalidNoise { name: &'static str },
+    UnreachableThreshold { threshold: usize, max: usize },
+}
+
+impl fmt::Display for MapConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapConfigError::TooSmall { width, height } => {
+                write!(
+                    f,
+                    "a {}x{} map is too small, it needs to be at least 2x2",
+                    width, height
+                )
+            }
+            MapConfigError::LevelOutOfRange { name, value } => {
+                write!(
+                    f,
+                    "{} is {}, but it needs to be between 0 and 1",
+                    name, value
+                )
+            }
+            MapConfigError::InvalidNoise { name } => write!(f, "{} needs to be positive", name),
+            MapConfigError::UnreachableThreshold { threshold, max } => write!(
+                f,
+                "walkable_tile_threshold is {}, but this map size and water level allow between 2 \
+                and {} walkable tiles",
+                threshold, max
+            ),
+        }
+    }
+}
+
+impl MapConfig {
+    pub fn validate(&self) -> Result<(), MapConfigError> {
+        if self.width < 2 || self.height < 2 {
+            return Err(MapConfigError::TooSmall {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        for &(name, value) in [
+            ("water_level", self.water_level),
+            ("shallows_depth", self.shallows_depth),
+            ("beach_width", self.beach_width),
+            ("forest_level", self.forest_level),
+            ("mountain_level", self.mountain_level),
+            ("river_source_level", self.river_source_level),
+        ]
+        .iter()
+        {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(MapConfigError::LevelOutOfRange { name, value });
+            }
+        }
+
+        if self.octave_count == 0 {
+            return Err(MapConfigError::InvalidNoise {
+                name: "octave_count",
+            });
+        }
+        for &(name, value) in [
+            ("octave_scale", self.octave_scale),
+            ("octave_persistence", self.octave_persistence),
+            ("moisture_scale", self.moisture_scale),
+        ]
+        .iter()
+        {
+            if value <= 0.0 {
+                return Err(MapConfigError::InvalidNoise { name });
+            }
+        }
+
+        let max = self.max_walkable_tiles();
+        if self.walkable_tile_threshold < 2 || self.walkable_tile_threshold > max {
+            return Err(MapConfigError::UnreachableThreshold {
+                threshold: self.walkable_tile_threshold,
+                max,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Upper bound on the size of the largest landmass any seed can produce. Noise never goes
+    /// above 1, so a tile can only be above deep water where the island's shape mask alone is.
+    pub fn max_walkable_tiles(&self) -> usize {
+        let mask = self.shape.mask();
+        let tiles = HeightMap::positions(self.width, self.height)
+            .map(|(x, y)| {
+                if mask.value(self.width, self.height, x, y)
+                    < self.water_level - self.shallows_depth
+                {
+                    TileType::DeepWater
+                } else {
+                    TileType::Grass
+                }
+            })
+            .collect();
+        let grid = TileGrid::new(self.width, self.height, tiles);
+
+        walkable_components(&grid).first().map_or(0, Vec::len)
+    }
+
+    /// The configuration for a campaign level. Later levels are larger and wetter, with
+    /// proportionally more walkable land required.
+    pub fn for_level(&self, level: u32) -> MapConfig {
+        let step = level.max(1) - 1;
+        let width = self.width + (5 * step).min(30);
+        let height = self.height + (5 * step).min(30);
+        let area_ratio = (width * height) as f64 / (self.width * self.height) as f64;
+
+        let mut config = MapConfig {
+            width,
+            height,
+            water_level: (self.water_level + 0.015 * step as f64).min(self.water_level + 0.09),
+            walkable_tile_threshold: (self.walkable_tile_threshold as f64 * area_ratio) as usize,
+            ..self.clone()
+        };
+        if step > 0 {
+            // Keep later levels possible even if the base threshold is close to the limit.
+            config.walkable_tile_threshold = config
+                .walkable_tile_threshold
+                .min(config.max_walkable_tiles() / 2)
+                .max(2);
+        }
+        config
+    }
+}
+
+/// Controls how far from the spawn the exit is placed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Range of walking distance percentiles, among all reachable tiles, that the exit is picked
+    /// from.
+    pub fn exit_percentiles(&self) -> (usize, usize) {
+        match self {
+            Difficulty::Easy => (40, 60),
+            Difficulty::Normal => (60, 80),
+            Difficulty::Hard => (100, 100),
+        }
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty {}", s)),
+        }
+    }
+}
+
+/// Terrain of a generated map, laid out in the same offset columns as the spawned tiles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileGrid {
+    width: u32,
+    height: u32,
+    tiles: Vec<TileType>,
+}
+
+impl TileGrid {
+    pub fn new(width: u32, height: u32, tiles: Vec<TileType>) -> Self {
+        assert_eq!(tiles.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            tiles,
+        }
+    }
+
+    fn from_height_map(
+        height_map: &HeightMap,
+        moisture_map: &MoistureMap,
+        config: &MapConfig,
+    ) -> Self {
+        let width = height_map.width;
+        let height = height_map.height;
+        let mut tiles = Vec::with_capacity(width as usize * height as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                tiles.push(biome(height_map.get(x, y), moisture_map.get(x, y), config));
+            }
+        }
+
+        Self::new(width, height, tiles)
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> TileType {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    /// Every (`x`, `y`) position on the grid, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = (u32, u32)> {
+        HeightMap::positions(self.width, self.height)
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, tile_type: TileType) {
+        self.tiles[(y * self.width + x) as usize] = tile_type;
+    }
+
+    pub fn walkable_count(&self) -> usize {
+        self.tiles.iter().filter(|tile| tile.is_walkable()).count()
+    }
+
+    pub fn is_walkable(&self, x: u32, y: u32) -> bool {
+        self.get(x, y).is_walkable()
+    }
+
+    pub fn hex(&self, x: u32, y: u32) -> HexCoord {
+        HexCoord::from_offset(x as i32, y as i32)
+    }
+
+    /// Grid position of `hex`, if it is on the grid.
+    pub fn position(&self, hex: HexCoord) -> Option<(u32, u32)> {
+        let (x, y) = hex.to_offset();
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            Some((x as u32, y as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the tiles match the dimensions, which a deserialized grid may not.
+    pub fn is_complete(&self) -> bool {
+        self.tiles.len() == self.width as usize * self.height as usize
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Positions of the tiles adjacent to (`x`, `y`) that are on the grid.
+    pub fn neighbors(&self, x: u32, y: u32) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.hex(x, y)
+            .neighbors()
+            .filter_map(move |neighbor| self.position(neighbor))
+    }
+}
+
+/// How many height maps are tried before giving up on reaching the walkable tile threshold.
+pub const MAX_GENERATION_ATTEMPTS: u32 = 100;
+
+/// A generated grid and its largest landmass.
+pub type GeneratedIsland = (TileGrid, Vec<(u32, u32)>);
+
+#[derive(Debug)]
+pub struct GenerationError {
+    pub attempts: u32,
+    pub threshold: usize,
+    /// The attempt with the largest landmass, if any had one big enough to place both the player
+    /// and the exit on.
+    pub best: Option<GeneratedIsland>,
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let best = self.best.as_ref().map_or(0, |(_, landmass)| landmass.len());
+        write!(
+            f,
+            "No island with at least {} walkable tiles after {} attempts; the largest had {}",
+            self.threshold, self.attempts, best
+        )
+    }
+}
+
+/// Statistics about one generated height map, whether or not it was accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationAttempt {
+    pub attempt: u32,
+    /// Fraction of all tiles that are walkable.
+    pub walkable_ratio: f64,
+    pub landmasses: usize,
+    /// Walkable tiles in the largest landmass.
+    pub largest_landmass: usize,
+}
+
+/// Generates islands from `rng` until one has a landmass with at least the configured number of
+/// walkable tiles, returning its grid and that landmass. Gives up after
+/// `MAX_GENERATION_ATTEMPTS`. `on_attempt` is called after every height map, e.g. for logging.
+pub fn generate_island(
+    config: &MapConfig,
+    rng: &mut impl Rng,
+    mut on_attempt: impl FnMut(&GenerationAttempt),
+) -> Result<GeneratedIsland, GenerationError> {
+    let mut best: Option<GeneratedIsland> = None;
+
+    for attempt in 1..=MAX_GENERATION_ATTEMPTS {
+        let height_map = HeightMap::new(config, rng.gen());
+        let moisture_map = MoistureMap::new(config, rng.gen());
+        let mut grid = TileGrid::from_height_map(&height_map, &moisture_map, config);
+        carve_rivers(&mut grid, |x, y| height_map.get(x, y), config, rng);
+        let mut components = walkable_components(&grid);
+        let landmass = if components.is_empty() {
+            Vec::new()
+        } else {
+            components.swap_remove(0)
+        };
+
+        on_attempt(&GenerationAttempt {
+            attempt,
+            walkable_ratio: grid.walkable_count() as f64 / grid.tiles.len() as f64,
+            landmasses: components.len() + !landmass.is_empty() as usize,
+            largest_landmass: landmass.len(),
+        });
+
+        if landmass.len() >= config.walkable_tile_threshold {
+            return Ok((grid, landmass));
+        }
+        let is_best = best.as_ref().map_or(true, |(_, best_landmass)| {
+            landmass.len() > best_landmass.len()
+        });
+        if landmass.len() >= 2 && is_best {
+            best = Some((grid, landmass));
+        }
+    }
+
+    Err(GenerationError {
+        attempts: MAX_GENERATION_ATTEMPTS,
+        threshold: config.walkable_tile_threshold,
+        best,
+    })
+}
+
+/// Groups the walkable tiles of `grid` into landmasses connected through walkable neighbors,
+/// ordered from largest to smallest.
+pub fn walkable_components(grid: &TileGrid) -> Vec<Vec<(u32, u32)>> {
+    let mut visited = vec![false; grid.tiles.len()];
+    let mut components = Vec::new();
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let index = (y * grid.width + x) as usize;
+            if visited[index] || !grid.is_walkable(x, y) {
+                continue;
+            }
+
+            visited[index] = true;
+            let mut component = vec![(x, y)];
+            let mut next = 0;
+
+            while next < component.len() {
+                let (cx, cy) = component[next];
+                next += 1;
+
+                for (nx, ny) in grid.neighbors(cx, cy) {
+                    let neighbor_index = (ny * grid.width + nx) as usize;
+                    if !visited[neighbor_index] && grid.is_walkable(nx, ny) {
+                        visited[neighbor_index] = true;
+                        component.push((nx, ny));
+                    }
+                }
+            }
+
+            components.push(component);
+        }
+    }
+
+    components.sort_by(|a, b| b.len().cmp(&a.len()));
+    components
+}
+
+/// Walking distance in tiles from `start` to every tile of `grid`, indexed like the grid, or
+/// `None` for tiles that can't be reached.
+pub fn walking_distances(grid: &TileGrid, start: (u32, u32)) -> Vec<Option<u32>> {
+    let mut distances = vec![None; grid.tiles.len()];
+    let mut queue = VecDeque::new();
+
+    distances[(start.1 * grid.width + start.0) as usize] = Some(0);
+    queue.push_back((start, 0));
+
+    while let Some(((x, y), distance)) = queue.pop_front() {
+        for (nx, ny) in grid.neighbors(x, y) {
+            let index = (ny * grid.width + nx) as usize;
+            if distances[index].is_none() && grid.is_walkable(nx, ny) {
+                distances[index] = Some(distance + 1);
+                queue.push_back(((nx, ny), distance + 1));
+            }
+        }
+    }
+
+    distances
+}
+
+/// Picks an exit tile reachable from `spawn` whose walking distance falls within the percentile
+/// range of `difficulty`, returning it along with its distance.
+pub fn pick_exit(
+    grid: &TileGrid,
+    spawn: (u32, u32),
+    difficulty: Difficulty,
+    rng: &mut impl Rng,
+) -> Option<((u32, u32), u32)> {
+    let distances = walking_distances(grid, spawn);
+    let mut candidates: Vec<((u32, u32), u32)> = distances
+        .iter()
+        .enumerate()
+        .filter_map(|(index, distance)| match distance {
+            Some(d) if *d > 0 => {
+                let index = index as u32;
+                Some(((index % grid.width, index / grid.width), *d))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if candidates.is_empty() {
+        return None;
+    }
+
+    candidates.sort_by_key(|(_, distance)| *distance);
+
+    let (min_percentile, max_percentile) = difficulty.exit_percentiles();
+    let last = candidates.len() - 1;
+    let min_index = last * min_percentile / 100;
+    let max_index = last * max_percentile / 100;
+
+    Some(candidates[rng.gen_range(min_index..=max_index)])
+}
+
+pub struct HeightMap {
+    noise: OpenSimplex,
+    mask: Box<dyn ShapeMask>,
+    width: u32,
+    height: u32,
+    octave_scale: f64,
+    octave_count: usize,
+    octave_persistence: f64,
+    map: Vec<f64>,
+}
+
+impl HeightMap {
+    fn new(config: &MapConfig, seed: u32) -> Self {
+        let noise = OpenSimplex::new().set_seed(seed);
+        let mut height_map = Self {
+            noise,
+            mask: config.shape.mask(),
+            width: config.width,
+            height: config.height,
+            octave_scale: config.octave_scale,
+            octave_count: config.octave_count,
+            octave_persistence: config.octave_persistence,
+            map: vec![0.0; config.width as usize * config.height as usize],
+        };
+
+        height_map.generate();
+
+        height_map
+    }
+
+    fn positions(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+        (0..height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    fn generate(&mut self) {
+        for (x, y) in Self::positions(self.width, self.height) {
+            let index = (y * self.width + x) as usize;
+            self.map[index] = self.raw_value(x, y).clamp(0.0, 1.0);
+        }
+    }
+
+    /// The height at `x`, `y` before clamping: the octave noise scaled by the shape mask.
+    fn raw_value(&self, x: u32, y: u32) -> f64 {
+        self.sum_octave(x, y) * self.get_base_value(x, y)
+    }
+
+    fn get(&self, x: u32, y: u32) -> f64 {
+        let index = (y * self.width + x) as usize;
+        self.map[index]
+    }
+
+    fn get_base_value(&self, x: u32, y: u32) -> f64 {
+        self.mask.value(self.width, self.height, x, y)
+    }
+
+    fn sum_octave(&self, x: u32, y: u32) -> f64 {
+        sum_octave(
+            &self.noise,
+            x,
+            y,
+            self.octave_scale,
+            self.octave_count,
+            self.octave_persistence,
+        )
+    }
+}
+
+/// How wet each tile is, from a noise field independent of the height map.
+pub struct MoistureMap {
+    width: u32,
+    map: Vec<f64>,
+}
+
+impl MoistureMap {
+    fn new(config: &MapConfig, seed: u32) -> Self {
+        let noise = OpenSimplex::new().set_seed(seed);
+        let map = HeightMap::positions(config.width, config.height)
+            .map(|(x, y)| {
+                let value = sum_octave(
+                    &noise,
+                    x,
+                    y,
+                    config.moisture_scale,
+                    config.octave_count,
+                    config.octave_persistence,
+                );
+                value.clamp(0.0, 1.0)
+            })
+            .collect();
+
+        Self {
+            width: config.width,
+            map,
+        }
+    }
+
+    fn get(&self, x: u32, y: u32) -> f64 {
+        let index = (y * self.width + x) as usize;
+        self.map[index]
+    }
+}
+
+fn sum_octave(
+    noise: &OpenSimplex,
+    x: u32,
+    y: u32,
+    octave_scale: f64,
+    octave_count: usize,
+    octave_persistence: f64,
+) -> f64 {
+    let noise_min = 0.0;
+    let noise_max = 1.0;
+    let mut max_amp = 0.0;
+    let mut amp = 1.0;
+    let mut freq = octave_scale;
+    let mut value = 0.0;
+
+    for _ in 0..octave_count {
+        value += noise.get([x as f64 * freq, y as f64 * freq]) * amp;
+        max_amp += amp;
+        amp *= octave_persistence;
+        freq *= 2.0;
+    }
+
+    // Take the average noise value of the iterations
+    value /= max_amp;
+
+    // Normalize the result
+    value = value * (noise_max - noise_min) / 2.0 + (noise_max + noise_min) / 2.0;
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    fn grid_from_str(rows: &[&str]) -> TileGrid {
+        let tiles = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => TileType::Grass,
+                _ => TileType::DeepWater,
+            })
+            .collect();
+
+        TileGrid::new(rows[0].len() as u32, rows.len() as u32, tiles)
+    }
+
+    #[test]
+    fn test_walkable_components_separated_by_water() {
+        let grid = grid_from_str(&["##..#", "##..#", "....#"]);
+        let components = walkable_components(&grid);
+
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].len(), 4);
+        assert_eq!(components[1].len(), 3);
+        assert!(components[1].contains(&(4, 0)));
+    }
+
+    #[test]
+    fn test_walkable_components_diagonal_neighbors() {
+        // Odd columns are shifted half a tile along y, so (1, 0) touches (0, 0) and (0, 1)
+        // while (1, 1) touches (0, 1) and (0, 2).
+        let grid = grid_from_str(&[".#", "#.", "..", ".#"]);
+        let components = walkable_components(&grid);
+
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].len(), 2);
+        assert!(components[0].contains(&(1, 0)));
+        assert!(components[0].contains(&(0, 1)));
+    }
+
+    #[test]
+    fn test_map_config_scales_with_level() {
+        let config = MapConfig::default();
+        assert_eq!(config.for_level(1), config);
+
+        let mut previous = config.clone();
+        for level in 2..20 {
+            let level_config = config.for_level(level);
+            assert!(level_config.width >= previous.width);
+            assert!(level_config.water_level >= previous.water_level);
+            assert!(level_config.walkable_tile_threshold >= previous.walkable_tile_threshold);
+            assert_eq!(level_config.validate(), Ok(()));
+            previous = level_config;
+        }
+    }
+
+    fn height_map_config(width: u32, height: u32) -> MapConfig {
+        MapConfig {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_height_map_fills_configured_dimensions() {
+        let config = height_map_config(40, 25);
+        let height_map = HeightMap::new(&config, 83721);
+        assert_eq!(height_map.map.len(), 40 * 25);
+
+        let moisture_map = MoistureMap::new(&config, 83721);
+        let grid = TileGrid::from_height_map(&height_map, &moisture_map, &config);
+        assert_eq!((grid.width, grid.height), (40, 25));
+        assert!(grid.is_complete());
+
+        // The last column and row are generated like the rest, so at least some of the middle of
+        // each edge is above the lowest possible height.
+        assert!((0..25).any(|y| height_map.get(39, y) > 0.0));
+        assert!((0..40).any(|x| height_map.get(x, 24) > 0.0));
+    }
+
+    #[test]
+    fn test_height_map_values_in_range() {
+        for &(width, height) in [(40, 40), (60, 20), (2, 2)].iter() {
+            let height_map = HeightMap::new(&height_map_config(width, height), 83721);
+            assert!(HeightMap::positions(width, height)
+                .all(|(x, y)| (0.0..=1.0).contains(&height_map.raw_value(x, y))));
+        }
+    }
+
+    #[test]
+    fn test_height_map_is_deterministic() {
+        let config = height_map_config(30, 30);
+        let first = HeightMap::new(&config, 83721);
+        let second = HeightMap::new(&config, 83721);
+        let other = HeightMap::new(&config, 12345);
+
+        assert_eq!(first.map, second.map);
+        assert_ne!(first.map, other.map);
+    }
+
+    #[test]
+    fn test_generate_island_reaches_threshold() {
+        let config = MapConfig::default();
+        let mut rng = StdRng::seed_from_u64(83721);
+        let (grid, landmass) = generate_island(&config, &mut rng, |_| {}).unwrap();
+
+        assert!(landmass.len() >= config.walkable_tile_threshold);
+        assert!(landmass.iter().all(|&(x, y)| grid.is_walkable(x, y)));
+    }
+
+    #[test]
+    fn test_every_shape_generates_an_island() {
+        for shape in ["radial", "hexagonal", "archipelago", "atoll", "crescent"].iter() {
+            let config = MapConfig {
+                shape: shape.parse().unwrap(),
+                walkable_tile_threshold: 60,
+                ..Default::default()
+            };
+            assert_eq!(config.validate(), Ok(()));
+
+            let mut rng = StdRng::seed_from_u64(83721);
+            assert!(
+                generate_island(&config, &mut rng, |_| {}).is_ok(),
+                "{}",
+                shape
+            );
+        }
+    }
+
+    #[test]
+    fn test_generate_island_gives_up() {
+        // Valid, but far more land than the noise ever produces at this water level.
+        let config = MapConfig {
+            width: 10,
+            height: 10,
+            water_level: 0.6,
+            walkable_tile_threshold: 2,
+            ..Default::default()
+        };
+        let config = MapConfig {
+            walkable_tile_threshold: config.max_walkable_tiles(),
+            ..config
+        };
+        let mut rng = StdRng::seed_from_u64(83721);
+        let err = generate_island(&config, &mut rng, |_| {}).unwrap_err();
+
+        assert_eq!(err.attempts, MAX_GENERATION_ATTEMPTS);
+        assert!(err.best.map_or(true, |(_, landmass)| landmass.len()
+            < config.walkable_tile_threshold));
+    }
+
+    #[test]
+    fn test_map_config_validation() {
+        assert_eq!(MapConfig::default().validate(), Ok(()));
+
+        let too_small = MapConfig {
+            width: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            too_small.validate(),
+            Err(MapConfigError::TooSmall { .. })
+        ));
+
+        let flooded = MapConfig {
+            water_level: 1.5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            flooded.validate(),
+            Err(MapConfigError::LevelOutOfRange {
+                name: "water_level",
+                ..
+            })
+        ));
+
+        let impossible = MapConfig {
+            width: 10,
+            height: 10,
+            walkable_tile_threshold: 100,
+            ..Default::default()
+        };
+        assert!(matches!(
+            impossible.validate(),
+            Err(MapConfigError::UnreachableThreshold { threshold: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn test_walking_distances() {
+        let grid = grid_from_str(&["#.#", "###"]);
+        let distances = walking_distances(&grid, (0, 0));
+
+        assert_eq!(
+            distances,
+            vec![Some(0), None, Some(4), Some(1), Some(2), Some(3)]
+        );
+    }
+
+    #[test]
+    fn test_pick_exit_by_difficulty() {
+        let grid = grid_from_str(&["#"; 11]);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..20 {
+            let (_, distance) = pick_exit(&grid, (0, 0), Difficulty::Easy, &mut rng).unwrap();
+            assert!((4..=6).contains(&distance));
+
+            let (exit, distance) = pick_exit(&grid, (0, 0), Difficulty::Hard, &mut rng).unwrap();
+            assert_eq!(exit, (0, 10));
+            assert_eq!(distance, 10);
+        }
+    }
+
+    #[test]
+    fn test_pick_exit_without_other_tiles() {
+        let grid = grid_from_str(&["#."]);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert!(pick_exit(&grid, (0, 0), Difficulty::Hard, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_walkable_components_empty() {
+        let grid = grid_from_str(&["...", "..."]);
+        assert!(walkable_components(&grid).is_empty());
+    }
+}
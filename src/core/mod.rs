@@ -0,0 +1,9 @@
+pub mod ascii;
+pub mod biome;
+pub mod hex;
+pub mod hydrology;
+pub mod island;
+pub mod map;
+pub mod shape;
+
+pub use island::{Island, MoveError, MoveOutcome};
@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+/// Overall silhouette of an island. The height map's noise is scaled by the mask, so tiles where
+/// the mask is low end up under water.
+pub trait ShapeMask {
+    /// Mask value between 0 and 1 at position (`x`, `y`) of a `width` by `height` map.
+    fn value(&self, width: u32, height: u32, x: u32, y: u32) -> f64;
+}
+
+/// The built-in shape masks, as chosen in the map configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IslandShape {
+    /// A single round island in the middle of the map.
+    Radial,
+    /// A single island shaped like one of the map's hexagons.
+    Hexagonal,
+    /// Several smaller islands spread around the middle of the map.
+    Archipelago { islands: u32 },
+    /// A ring of land around a lagoon.
+    Atoll,
+    /// A round island with a bay bitten out of one side.
+    Crescent,
+}
+
+impl Default for IslandShape {
+    fn default() -> Self {
+        IslandShape::Radial
+    }
+}
+
+/// Parses shape names as given on the command line, e.g. `atoll` or `archipelago:5`.
+impl FromStr for IslandShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let mut parts = s.splitn(2, ':');
+        match (parts.next().unwrap_or_default(), parts.next()) {
+            ("radial", None) => Ok(IslandShape::Radial),
+            ("hexagonal", None) => Ok(IslandShape::Hexagonal),
+            ("archipelago", None) => Ok(IslandShape::Archipelago { islands: 4 }),
+            ("archipelago", Some(islands)) => islands
+                .parse()
+                .map(|islands| IslandShape::Archipelago { islands })
+                .map_err(|_| format!("invalid number of islands {}", islands)),
+            ("atoll", None) => Ok(IslandShape::Atoll),
+            ("crescent", None) => Ok(IslandShape::Crescent),
+            _ => Err(format!("unknown island shape {}", s)),
+        }
+    }
+}
+
+impl IslandShape {
+    pub fn mask(&self) -> Box<dyn ShapeMask> {
+        match self {
+            IslandShape::Radial => Box::new(RadialMask),
+            IslandShape::Hexagonal => Box::new(HexagonalMask),
+            IslandShape::Archipelago { islands } => Box::new(ArchipelagoMask::new(*islands)),
+            IslandShape::Atoll => Box::new(AtollMask),
+            IslandShape::Crescent => Box::new(CrescentMask),
+        }
+    }
+}
+
+/// Position relative to the middle of the map, scaled so that the edges are at -1 and 1.
+fn normalized(width: u32, height: u32, x: u32, y: u32) -> (f64, f64) {
+    let center_x = width as f64 / 2.0;
+    let center_y = height as f64 / 2.0;
+
+    (
+        (x as f64 - center_x) / center_x,
+        (y as f64 - center_y) / center_y,
+    )
+}
+
+/// Falls off from 1 at `distance` 0 to 0 at `radius` and beyond.
+fn falloff(distance: f64, radius: f64) -> f64 {
+    (1.0 - distance / radius).clamp(0.0, 1.0)
+}
+
+/// Falls off with the distance from the middle of the map, reaching 0 in the corners.
+pub struct RadialMask;
+
+impl ShapeMask for RadialMask {
+    fn value(&self, width: u32, height: u32, x: u32, y: u32) -> f64 {
+        let center_x = width as f64 / 2.0;
+        let center_y = height as f64 / 2.0;
+
+        let max_dist = (center_x * center_x + center_y * center_y).sqrt();
+
+        let dist_x = center_x - x as f64;
+        let dist_y = center_y - y as f64;
+
+        falloff((dist_x * dist_x + dist_y * dist_y).sqrt(), max_dist)
+    }
+}
+
+/// Falls off with the distance from the middle of the map measured in hexagon rings, reaching 0
+/// at the corners of a flat-top hexagon touching the left and right edges.
+pub struct HexagonalMask;
+
+impl ShapeMask for HexagonalMask {
+    fn value(&self, width: u32, height: u32, x: u32, y: u32) -> f64 {
+        let (x, y) = normalized(width, height, x, y);
+        falloff(y.abs().max(x.abs() + y.abs() / 2.0), 1.0)
+    }
+}
+
+/// A radial falloff around each of a number of centers, evenly spaced on a circle around the
+/// middle of the map.
+pub struct ArchipelagoMask {
+    centers: Vec<(f64, f64)>,
+}
+
+impl ArchipelagoMask {
+    const SPREAD: f64 = 0.6;
+    const ISLAND_RADIUS: f64 = 0.55;
+
+    pub fn new(islands: u32) -> Self {
+        let centers = match islands {
+            0 | 1 => vec![(0.0, 0.0)],
+            _ => (0..islands)
+                .map(|i| {
+                    let angle = PI / 2.0 + 2.0 * PI * i as f64 / islands as f64;
+                    (Self::SPREAD * angle.cos(), Self::SPREAD * angle.sin())
+                })
+                .collect(),
+        };
+
+        ArchipelagoMask { centers }
+    }
+}
+
+impl ShapeMask for ArchipelagoMask {
+    fn value(&self, width: u32, height: u32, x: u32, y: u32) -> f64 {
+        let (x, y) = normalized(width, height, x, y);
+        self.centers
+            .iter()
+            .map(|(center_x, center_y)| {
+                let distance = ((x - center_x).powi(2) + (y - center_y).powi(2)).sqrt();
+                falloff(distance, Self::ISLAND_RADIUS)
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Highest on a circle around the middle of the map, falling off both inwards and outwards.
+pub struct AtollMask;
+
+impl AtollMask {
+    const RING_RADIUS: f64 = 0.6;
+    const RING_WIDTH: f64 = 0.35;
+}
+
+impl ShapeMask for AtollMask {
+    fn value(&self, width: u32, height: u32, x: u32, y: u32) -> f64 {
+        let (x, y) = normalized(width, height, x, y);
+        let distance = (x * x + y * y).sqrt();
+        falloff((distance - Self::RING_RADIUS).abs(), Self::RING_WIDTH)
+    }
+}
+
+/// A radial falloff with a second, smaller circle off to one side pushed down below the water.
+pub struct CrescentMask;
+
+impl CrescentMask {
+    const RADIUS: f64 = 1.2;
+    const BITE_CENTER: (f64, f64) = (0.45, 0.0);
+    const BITE_RADIUS: f64 = 0.15;
+    const BITE_EDGE: f64 = 0.35;
+}
+
+impl ShapeMask for CrescentMask {
+    fn value(&self, width: u32, height: u32, x: u32, y: u32) -> f64 {
+        let (x, y) = normalized(width, height, x, y);
+        let island = falloff((x * x + y * y).sqrt(), Self::RADIUS);
+
+        let (bite_x, bite_y) = Self::BITE_CENTER;
+        let bite_distance = ((x - bite_x).powi(2) + (y - bite_y).powi(2)).sqrt();
+        let bite = ((bite_distance - Self::BITE_RADIUS) / Self::BITE_EDGE).clamp(0.0, 1.0);
+
+        island * bite
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::*;
+
+    const SHAPES: [IslandShape; 5] = [
+        IslandShape::Radial,
+        IslandShape::Hexagonal,
+        IslandShape::Archipelago { islands: 4 },
+        IslandShape::Atoll,
+        IslandShape::Crescent,
+    ];
+
+    #[test]
+    fn test_masks_in_range() {
+        for shape in SHAPES.iter() {
+            let mask = shape.mask();
+            for &(width, height) in [(40, 40), (60, 25)].iter() {
+                for y in 0..height {
+                    for x in 0..width {
+                        let value = mask.value(width, height, x, y);
+                        assert!((0.0..=1.0).contains(&value), "{:?} gave {}", shape, value);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_shapes() {
+        assert_eq!("Atoll".parse(), Ok(IslandShape::Atoll));
+        assert_eq!(
+            "archipelago".parse(),
+            Ok(IslandShape::Archipelago { islands: 4 })
+        );
+        assert_eq!(
+            "archipelago:6".parse(),
+            Ok(IslandShape::Archipelago { islands: 6 })
+        );
+        assert!("archipelago:lots".parse::<IslandShape>().is_err());
+        assert!("square".parse::<IslandShape>().is_err());
+    }
+
+    #[test]
+    fn test_radial_mask() {
+        let mask = RadialMask;
+        assert!(approx_eq!(f64, mask.value(40, 40, 20, 20), 1.0));
+        assert!(approx_eq!(f64, mask.value(40, 40, 0, 0), 0.0));
+
+        // The corners of non-square maps are at the edge of the falloff too.
+        assert!(approx_eq!(f64, mask.value(60, 20, 0, 0), 0.0));
+        assert!(mask.value(60, 20, 0, 10) > 0.0);
+    }
+
+    #[test]
+    fn test_atoll_mask_has_lagoon() {
+        let mask = AtollMask;
+        let middle = mask.value(40, 40, 20, 20);
+        let ring = mask.value(40, 40, 20 + 12, 20);
+        assert!(ring > middle);
+        assert!(approx_eq!(f64, ring, 1.0));
+    }
+
+    #[test]
+    fn test_archipelago_mask_separates_islands() {
+        let mask = ArchipelagoMask::new(4);
+        let first_island = mask.value(40, 40, 20, 20 + 12);
+        let between_islands = mask.value(40, 40, 20, 20);
+        assert!(first_island > 0.9);
+        assert!(between_islands < first_island / 2.0);
+    }
+
+    #[test]
+    fn test_crescent_mask_has_bay() {
+        let mask = CrescentMask;
+        assert!(mask.value(40, 40, 20 - 9, 20) > mask.value(40, 40, 20 + 9, 20));
+    }
+}
@@ -0,0 +1,435 @@
+use bevy::prelude::*;
+use image::{ImageResult, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::map::{sprite_for_tile_type, walkable_components, Island, TileType};
+use super::util::{find_path, Hex, HexCoord, TILE_SIZE};
+
+/// Roughly the average color of each tile sprite, for drawing tiles without them.
+const FLAT_COLORS: [(&str, [u8; 3]); 6] = [
+    ("grass.png", [53, 174, 102]),
+    ("water.png", [42, 58, 120]),
+    ("sand.png", [253, 200, 115]),
+    ("forest.png", [40, 125, 116]),
+    ("mountain.png", [106, 128, 150]),
+    ("exit.png", [68, 130, 170]),
+];
+const SPAWN_COLOR: [u8; 4] = [255, 220, 0, 255];
+const EXIT_COLOR: [u8; 4] = [255, 64, 255, 255];
+const PATH_COLOR: [u8; 4] = [177, 62, 83, 255];
+/// Cycled through for the walkable areas, drawn over their tiles at half opacity.
+const COMPONENT_COLORS: [[u8; 4]; 6] = [
+    [230, 25, 75, 128],
+    [60, 180, 75, 128],
+    [255, 225, 25, 128],
+    [0, 130, 200, 128],
+    [245, 130, 48, 128],
+    [145, 30, 180, 128],
+];
+
+/// Something drawn over an exported island.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Overlay {
+    Spawn,
+    Exit,
+    /// The fewest moves from the spawn to the exit.
+    Path,
+    /// Each separate walkable area in its own color.
+    Components,
+}
+
+impl FromStr for Overlay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spawn" => Ok(Overlay::Spawn),
+            "exit" => Ok(Overlay::Exit),
+            "path" => Ok(Overlay::Path),
+            "components" => Ok(Overlay::Components),
+            _ => Err(format!("unknown overlay {}", s)),
+        }
+    }
+}
+
+/// How an island is drawn when it is exported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    /// Size of each sprite pixel in the image, in pixels.
+    pub scale: u32,
+    /// Draws tiles in flat colors instead of with their sprites.
+    pub flat: bool,
+    pub overlays: Vec<Overlay>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1,
+            flat: false,
+            overlays: Vec::new(),
+        }
+    }
+}
+
+/// The tile sprites an island is drawn with, by file name.
+pub struct Sprites(HashMap<&'static str, RgbaImage>);
+
+impl Sprites {
+    /// Loads the sprites of every tile type on `island` from `dir`.
+    pub fn load(dir: &Path, island: &Island) -> ImageResult<Self> {
+        let mut sprites = HashMap::new();
+        let grid = island.grid();
+        for (x, y) in grid.positions() {
+            let (name, _) = sprite_for_tile_type(tile_type(island, grid.hex(x, y)));
+            if !sprites.contains_key(name) {
+                sprites.insert(name, image::open(dir.join(name))?.to_rgba8());
+            }
+        }
+        Ok(Sprites(sprites))
+    }
+}
+
+/// Where Bevy's asset server looks for the game's assets.
+fn assets_dir() -> PathBuf {
+    let root = match env::var("CARGO_MANIFEST_DIR") {
+        Ok(manifest_dir) => PathBuf::from(manifest_dir),
+        Err(_) => env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            .unwrap_or_default(),
+    };
+    root.join("assets")
+}
+
+/// Draws the whole of `island` to a PNG file at `path`, falling back to flat colors if the sprites
+/// can't be loaded.
+pub fn export_png(island: &Island, path: &Path, options: &ExportOptions) -> ImageResult<()> {
+    let sprites = if options.flat {
+        None
+    } else {
+        match Sprites::load(&assets_dir(), island) {
+            Ok(sprites) => Some(sprites),
+            Err(err) => {
+                warn!(
+                    "Could not load the tile sprites, using flat colors: {}",
+                    err
+                );
+                None
+            }
+        }
+    };
+
+    render(island, options, sprites.as_ref()).save(path)
+}
+
+/// Draws `island` the way the game lays it out, with north up and no fog, using `sprites` or
+/// flat colors if there are none.
+pub fn render(island: &Island, options: &ExportOptions, sprites: Option<&Sprites>) -> RgbaImage {
+    let scale = options.scale.max(1);
+    let tile_size = TILE_SIZE as u32 * scale;
+    let grid = island.grid();
+    let points: Vec<(HexCoord, (f32, f32))> = grid
+        .positions()
+        .map(|(x, y)| {
+            let hex = grid.hex(x, y);
+            (hex, Hex::from(hex).to_point(tile_size as f32))
+        })
+        .collect();
+
+    if points.is_empty() {
+        return RgbaImage::new(0, 0);
+    }
+    let min_x = points.iter().map(|(_, (x, _))| *x).fold(f32::MAX, f32::min);
+    let max_x = points.iter().map(|(_, (x, _))| *x).fold(f32::MIN, f32::max);
+    let min_y = points.iter().map(|(_, (_, y))| *y).fold(f32::MAX, f32::min);
+    let max_y = points.iter().map(|(_, (_, y))| *y).fold(f32::MIN, f32::max);
+
+    // Image rows go down while the game's y axis points north, so tiles are flipped vertically.
+    let corners: HashMap<HexCoord, (i64, i64)> = points
+        .iter()
+        .map(|(hex, (x, y))| {
+            let corner = ((x - min_x).round() as i64, (max_y - y).round() as i64);
+            (*hex, corner)
+        })
+        .collect();
+    let mut image = RgbaImage::new(
+        (max_x - min_x).round() as u32 + tile_size,
+        (max_y - min_y).round() as u32 + tile_size,
+    );
+
+    for (hex, corner) in &corners {
+        let (name, tint) = sprite_for_tile_type(tile_type(island, *hex));
+        let tint = [tint.r(), tint.g(), tint.b()];
+        let sprite = sprites.and_then(|sprites| sprites.0.get(name));
+        fill_tile(&mut image, *corner, scale, |x, y| {
+            let color = match sprite {
+                Some(sprite) => sprite.get_pixel(x, y).0,
+                None if in_hexagon(x, y) => {
+                    let [r, g, b] = flat_color(name);
+                    [r, g, b, 255]
+                }
+                None => return None,
+            };
+            Some([
+                (color[0] as f32 * tint[0]).round() as u8,
+                (color[1] as f32 * tint[1]).round() as u8,
+                (color[2] as f32 * tint[2]).round() as u8,
+                color[3],
+            ])
+        });
+    }
+
+    let center = |hex: HexCoord| {
+        let (x, y) = corners[&hex];
+        let half = tile_size as f32 / 2.0;
+        (x as f32 + half, y as f32 + half)
+    };
+    let tile_size = tile_size as f32;
+
+    if options.overlays.contains(&Overlay::Components) {
+        for (component, color) in walkable_components(grid)
+            .iter()
+            .zip(COMPONENT_COLORS.iter().cycle())
+        {
+            for (x, y) in component {
+                let corner = corners[&grid.hex(*x, *y)];
+                fill_tile(&mut image, corner, scale, |x, y| {
+                    if in_hexagon(x, y) {
+                        Some(*color)
+                    } else {
+                        None
+                    }
+                });
+            }
+        }
+    }
+    if options.overlays.contains(&Overlay::Path) {
+        // Every step counts the same, as in the island's exit distance that efficiency is scored
+        // against, rather than the quickest route over slow terrain.
+        let path = find_path(island.spawn(), island.exit(), |hex| {
+            island.movement_cost(hex).map(|_| 1)
+        });
+        for hex in path.unwrap_or_default() {
+            draw_circle(&mut image, center(hex), 0.0, tile_size / 6.0, PATH_COLOR);
+        }
+    }
+    // Rings, so the tiles they mark can still be made out.
+    let (ring_inner, ring_outer) = (tile_size / 2.5 - tile_size / 8.0, tile_size / 2.5);
+    if options.overlays.contains(&Overlay::Spawn) {
+        let center = center(island.spawn());
+        draw_circle(&mut image, center, ring_inner, ring_outer, SPAWN_COLOR);
+    }
+    if options.overlays.contains(&Overlay::Exit) {
+        let center = center(island.exit());
+        draw_circle(&mut image, center, ring_inner, ring_outer, EXIT_COLOR);
+    }
+
+    image
+}
+
+fn tile_type(island: &Island, hex: HexCoord) -> TileType {
+    island
+        .tile_type(hex)
+        .expect("Every grid position should have a tile.")
+}
+
+fn flat_color(sprite: &str) -> [u8; 3] {
+    FLAT_COLORS
+        .iter()
+        .find(|(name, _)| *name == sprite)
+        .map_or(FLAT_COLORS[0].1, |(_, color)| *color)
+}
+
+/// Whether the sprite pixel at `x`, `y` is inside the hexagon every tile sprite is shaped like.
+fn in_hexagon(x: u32, y: u32) -> bool {
+    let half = TILE_SIZE as f32 / 2.0;
+    let dx = (x as f32 + 0.5 - half).abs();
+    let dy = (y as f32 + 0.5 - half).abs();
+    dx <= half - dy / 2.0
+}
+
+/// Blends `pixel`'s color, if it has one, over each pixel of the tile sprite whose top-left corner
+/// is at `corner`.
+fn fill_tile(
+    image: &mut RgbaImage,
+    corner: (i64, i64),
+    scale: u32,
+    pixel: impl Fn(u32, u32) -> Option<[u8; 4]>,
+) {
+    for sprite_y in 0..TILE_SIZE as u32 {
+        for sprite_x in 0..TILE_SIZE as u32 {
+            let color = match pixel(sprite_x, sprite_y) {
+                Some(color) => color,
+                None => continue,
+            };
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let x = corner.0 + (sprite_x * scale + dx) as i64;
+                    let y = corner.1 + (sprite_y * scale + dy) as i64;
+                    blend(image, x, y, color);
+                }
+            }
+        }
+    }
+}
+
+/// Blends `color` over the pixels between `inner` and `outer` away from `center`.
+fn draw_circle(image: &mut RgbaImage, center: (f32, f32), inner: f32, outer: f32, color: [u8; 4]) {
+    let (left, right) = ((center.0 - outer).floor(), (center.0 + outer).ceil());
+    let (top, bottom) = ((center.1 - outer).floor(), (center.1 + outer).ceil());
+    for y in top as i64..bottom as i64 {
+        for x in left as i64..right as i64 {
+            let dx = x as f32 + 0.5 - center.0;
+            let dy = y as f32 + 0.5 - center.1;
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance >= inner && distance <= outer {
+                blend(image, x, y, color);
+            }
+        }
+    }
+}
+
+/// Draws `color` over the pixel at `x`, `y`, if it is in the image.
+fn blend(image: &mut RgbaImage, x: i64, y: i64, color: [u8; 4]) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let below = image.get_pixel(x as u32, y as u32).0;
+    let alpha = color[3] as f32 / 255.0;
+    let below_alpha = below[3] as f32 / 255.0 * (1.0 - alpha);
+    let out_alpha = alpha + below_alpha;
+    if out_alpha <= 0.0 {
+        return;
+    }
+
+    let mut out = [0; 4];
+    for ((out, color), below) in out.iter_mut().zip(&color).zip(&below).take(3) {
+        let value = *color as f32 * alpha + *below as f32 * below_alpha;
+        *out = (value / out_alpha).round() as u8;
+    }
+    out[3] = (out_alpha * 255.0).round() as u8;
+    image.put_pixel(x as u32, y as u32, Rgba(out));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileGrid;
+    use i_want_to_go_home::core::island::test_util::island;
+
+    fn flat(scale: u32, overlays: Vec<Overlay>) -> ExportOptions {
+        ExportOptions {
+            scale,
+            flat: true,
+            overlays,
+        }
+    }
+
+    /// The color at the middle of the sprite whose top-left corner is at `corner`.
+    fn middle(image: &RgbaImage, corner: (u32, u32), scale: u32) -> [u8; 4] {
+        let half = TILE_SIZE as u32 * scale / 2;
+        image.get_pixel(corner.0 + half, corner.1 + half).0
+    }
+
+    #[test]
+    fn test_render_flat() {
+        let island = island();
+
+        let image = render(&island, &flat(1, Vec::new()), None);
+
+        // The second column is 12 pixels to the right of the first and half a tile higher.
+        assert_eq!(image.dimensions(), (28, 56));
+        assert_eq!(middle(&image, (0, 40), 1), [53, 174, 102, 255]);
+        assert_eq!(middle(&image, (12, 16), 1), [42, 58, 120, 255]);
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+
+        let scaled = render(&island, &flat(3, Vec::new()), None);
+        assert_eq!(scaled.dimensions(), (84, 168));
+        assert_eq!(middle(&scaled, (0, 120), 3), [53, 174, 102, 255]);
+    }
+
+    #[test]
+    fn test_render_overlays() {
+        let island = island();
+        let plain = render(&island, &flat(1, Vec::new()), None);
+
+        let path = render(&island, &flat(1, vec![Overlay::Path]), None);
+        // The spawn, the exit, and the shallows on the way, but not the mountain.
+        let changed =
+            |image: &RgbaImage, corner| middle(image, corner, 1) != middle(&plain, corner, 1);
+        assert!(changed(&path, (0, 40)));
+        assert!(changed(&path, (12, 16)));
+        assert!(changed(&path, (12, 0)));
+        assert!(!changed(&path, (0, 8)));
+
+        let components = render(&island, &flat(1, vec![Overlay::Components]), None);
+        assert!(changed(&components, (12, 0)));
+        assert!(!changed(&components, (0, 8)));
+
+        let markers = render(&island, &flat(1, vec![Overlay::Spawn, Overlay::Exit]), None);
+        assert_eq!(markers.get_pixel(8 - 6, 48).0, SPAWN_COLOR);
+        assert_eq!(markers.get_pixel(12 + 8 - 6, 8).0, EXIT_COLOR);
+        assert_eq!(middle(&markers, (0, 40), 1), middle(&plain, (0, 40), 1));
+    }
+
+    #[test]
+    fn test_render_path_with_fewest_moves() {
+        // Straight through the shallows takes two moves, around them on the grass three, even
+        // though the grass is quicker to walk.
+        let mut tiles = vec![TileType::Grass; 6];
+        tiles[2] = TileType::Shallows;
+        let grid = TileGrid::new(2, 3, tiles);
+        let island = Island::new(grid, HexCoord::new(0, 0), HexCoord::from_offset(0, 2), 2, 0);
+        let plain = render(&island, &flat(1, Vec::new()), None);
+
+        let path = render(&island, &flat(1, vec![Overlay::Path]), None);
+
+        let changed = |corner| middle(&path, corner, 1) != middle(&plain, corner, 1);
+        assert!(changed((0, 24)));
+        assert!(!changed((12, 32)));
+        assert!(!changed((12, 16)));
+    }
+
+    #[test]
+    fn test_render_with_sprites() {
+        let island = island();
+        let sprites = Sprites::load(Path::new("assets"), &island).unwrap();
+
+        let image = render(&island, &ExportOptions::default(), Some(&sprites));
+
+        let grass = image::open("assets/grass.png").unwrap().to_rgba8();
+        assert_eq!(image.get_pixel(4, 40).0, grass.get_pixel(4, 0).0);
+        assert_eq!(image.get_pixel(0, 40).0[3], 0);
+    }
+
+    #[test]
+    fn test_every_tile_type_has_a_sprite() {
+        let tile_types = [
+            TileType::DeepWater,
+            TileType::Shallows,
+            TileType::River,
+            TileType::Ford,
+            TileType::Sand,
+            TileType::Grass,
+            TileType::Forest,
+            TileType::Mountain,
+            TileType::Exit,
+        ];
+        for &tile_type in tile_types.iter() {
+            let (name, _) = sprite_for_tile_type(tile_type);
+            assert!(image::open(Path::new("assets").join(name)).is_ok());
+            assert!(FLAT_COLORS.iter().any(|(flat_name, _)| *flat_name == name));
+        }
+    }
+
+    #[test]
+    fn test_parse_overlay() {
+        assert_eq!("Path".parse(), Ok(Overlay::Path));
+        assert_eq!("components".parse(), Ok(Overlay::Components));
+        assert!("player".parse::<Overlay>().is_err());
+    }
+}
@@ -1,19 +1,164 @@
 use bevy::prelude::*;
 
-#[derive(Default)]
+pub use i_want_to_go_home::core::map::Difficulty;
+
+use super::settings::Settings;
+
+/// Which screen the game is on. Gameplay systems only run while `Playing`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AppState {
+    MainMenu,
+    Playing,
+    Paused,
+    Won,
+}
+
 pub struct Game {
-    pub won: bool,
+    /// Campaign level of the current island, starting from 1.
+    pub level: u32,
+    /// Moves taken on every island of the run so far.
+    pub total_moves: u32,
+    /// Seconds spent playing every island of the run so far.
+    pub total_time: f32,
+    /// Moves taken on the current island.
+    pub moves: u32,
+    /// Seconds spent playing the current island.
+    pub time: f32,
+    /// Walkable tiles of the current island the player has seen.
+    pub tiles_revealed: usize,
+    /// Walkable tiles on the current island.
+    pub walkable_tiles: usize,
+    /// Walking distance in tiles from the spawn to the exit, which is the fewest moves that can
+    /// reach it.
+    pub exit_distance: u32,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game {
+            level: 1,
+            total_moves: 0,
+            total_time: 0.0,
+            moves: 0,
+            time: 0.0,
+            tiles_revealed: 0,
+            walkable_tiles: 0,
+            exit_distance: 0,
+        }
+    }
+}
+
+impl Game {
+    /// Resets the stats kept for a single island, keeping the run totals.
+    pub fn start_island(&mut self, walkable_tiles: usize) {
+        self.moves = 0;
+        self.time = 0.0;
+        self.tiles_revealed = 0;
+        self.walkable_tiles = walkable_tiles;
+    }
+
+    /// The fewest possible moves divided by the moves taken on the current island, from 0 to 1.
+    pub fn efficiency(&self) -> f32 {
+        if self.moves == 0 {
+            return 1.0;
+        }
+        (self.exit_distance as f32 / self.moves as f32).min(1.0)
+    }
+}
+
+/// How many islands make up a run. Reaching the exit of every island but the last one moves on to
+/// the next level.
+pub struct Campaign {
+    pub levels: u32,
+}
+
+impl Campaign {
+    pub fn is_campaign(&self) -> bool {
+        self.levels > 1
+    }
+
+    pub fn is_last_level(&self, game: &Game) -> bool {
+        game.level >= self.levels
+    }
 }
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup_game);
+        let default_settings = Settings::default();
+        let settings = app
+            .world
+            .get_resource::<Settings>()
+            .unwrap_or(&default_settings);
+        let difficulty = settings.difficulty;
+        let campaign = Campaign {
+            levels: settings.campaign_levels.max(1),
+        };
+
+        app.insert_resource(difficulty)
+            .insert_resource(campaign)
+            .add_state(AppState::MainMenu)
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(track_time));
     }
 }
 
-fn setup_game(mut game: ResMut<Game>) {
-    game.won = false;
+fn track_time(time: Res<Time>, mut game: ResMut<Game>) {
+    game.time += time.delta_seconds();
+    game.total_time += time.delta_seconds();
 }
 
+/// Formats a number of seconds as minutes and seconds, e.g. `2:05`.
+pub fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::*;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0.0), "0:00");
+        assert_eq!(format_time(9.7), "0:09");
+        assert_eq!(format_time(125.0), "2:05");
+        assert_eq!(format_time(3600.0), "60:00");
+    }
+
+    #[test]
+    fn test_efficiency() {
+        let mut game = Game {
+            exit_distance: 12,
+            ..Default::default()
+        };
+        assert!(approx_eq!(f32, game.efficiency(), 1.0));
+
+        game.moves = 12;
+        assert!(approx_eq!(f32, game.efficiency(), 1.0));
+
+        game.moves = 48;
+        assert!(approx_eq!(f32, game.efficiency(), 0.25));
+    }
+
+    #[test]
+    fn test_start_island_keeps_totals() {
+        let mut game = Game {
+            total_moves: 30,
+            total_time: 60.0,
+            moves: 10,
+            time: 20.0,
+            tiles_revealed: 15,
+            ..Default::default()
+        };
+        game.start_island(400);
+
+        assert_eq!(game.moves, 0);
+        assert!(approx_eq!(f32, game.time, 0.0));
+        assert_eq!(game.tiles_revealed, 0);
+        assert_eq!(game.walkable_tiles, 400);
+        assert_eq!(game.total_moves, 30);
+        assert!(approx_eq!(f32, game.total_time, 60.0));
+    }
+}
@@ -0,0 +1,143 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use i_want_to_go_home::core::ascii;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use super::export::export_png;
+use super::game::{AppState, Game};
+use super::map::{Island, MapSeed};
+use super::player::{PlayerSystem, StepEvent};
+use super::settings::Settings;
+use super::util::{HexCoord, HexDirection};
+
+/// Steps still to be taken in a headless run, one per frame.
+struct ScriptedMoves(VecDeque<HexDirection>);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+enum Outcome {
+    /// The player made it home from the last island.
+    Won,
+    /// The moves ran out before the player made it home.
+    Stranded,
+}
+
+/// Printed when a headless run ends.
+#[derive(Serialize)]
+struct Report {
+    outcome: Outcome,
+    seed: u64,
+    level: u32,
+    moves: u32,
+    total_moves: u32,
+    tiles_revealed: usize,
+    player: HexCoord,
+    exit: HexCoord,
+}
+
+/// Plays the moves from the settings without a window, then prints how the run ended, the island
+/// too with `--dump-map`, writes it to a PNG with `--export-png`, and exits.
+pub struct HeadlessPlugin;
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let moves = app
+            .world
+            .get_resource::<Settings>()
+            .map(|settings| settings.moves.iter().copied().collect())
+            .unwrap_or_default();
+
+        app.insert_resource(ScriptedMoves(moves))
+            .add_startup_system(start_playing)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(scripted_input_system.before(PlayerSystem::Movement)),
+            )
+            .add_system_to_stage(CoreStage::Last, report_system);
+    }
+}
+
+/// Runs the game in the terminal: the real game systems over `MinimalPlugins`, with the
+/// rendering, input, audio, saving and high scores left out.
+pub fn run() {
+    App::new()
+        .init_resource::<Game>()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(bevy::log::LogPlugin)
+        .add_plugin(super::settings::SettingsPlugin)
+        .add_plugin(super::game::GamePlugin)
+        .add_plugin(super::map::MapPlugin)
+        .add_plugin(super::player::PlayerPlugin)
+        .add_plugin(HeadlessPlugin)
+        .run();
+}
+
+/// Skips the title screen.
+fn start_playing(mut state: ResMut<State<AppState>>) {
+    state.overwrite_set(AppState::Playing).unwrap();
+}
+
+fn scripted_input_system(
+    mut moves: ResMut<ScriptedMoves>,
+    mut step_events: EventWriter<StepEvent>,
+) {
+    if let Some(direction) = moves.0.pop_front() {
+        step_events.send(StepEvent(direction));
+    }
+}
+
+/// Ends the run once the player has made it home, or once every scripted move has been played.
+fn report_system(
+    state: Res<State<AppState>>,
+    moves: Res<ScriptedMoves>,
+    island: Res<Island>,
+    seed: Res<MapSeed>,
+    game: Res<Game>,
+    settings: Res<Settings>,
+    mut exit_events: EventWriter<AppExit>,
+    mut reported: Local<bool>,
+) {
+    let outcome = match state.current() {
+        AppState::Won => Outcome::Won,
+        AppState::Playing if moves.0.is_empty() && !island.has_escaped() => Outcome::Stranded,
+        _ => return,
+    };
+    if *reported {
+        return;
+    }
+    *reported = true;
+
+    if settings.dump_map {
+        // After a scripted run, show only what the player got to see.
+        println!("{}", ascii::render(&island, settings.headless));
+        println!("{}", ascii::legend());
+    }
+    if let Some(path) = &settings.export_png {
+        match export_png(&island, path, &settings.export) {
+            Ok(()) => info!("Exported the island to {}", path.display()),
+            Err(err) => error!("Could not export the island to {}: {}", path.display(), err),
+        }
+    }
+    if settings.headless {
+        print_report(outcome, &island, &seed, &game);
+    }
+    exit_events.send(AppExit);
+}
+
+fn print_report(outcome: Outcome, island: &Island, seed: &MapSeed, game: &Game) {
+    let report = Report {
+        outcome,
+        seed: seed.0,
+        level: game.level,
+        moves: game.moves,
+        total_moves: game.total_moves,
+        tiles_revealed: game.tiles_revealed,
+        player: island.player(),
+        exit: island.exit(),
+    };
+    println!(
+        "{}",
+        ron::ser::to_string_pretty(&report, Default::default())
+            .expect("Reports should always serialize.")
+    );
+}
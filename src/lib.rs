@@ -0,0 +1,4 @@
+//! Island generation and the rules of play, kept free of Bevy so tools can use them without a
+//! window.
+
+pub mod core;
@@ -1,18 +1,44 @@
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::ecs::component::Component;
 use bevy::input::keyboard::KeyboardInput;
 use bevy::input::mouse::MouseButtonInput;
 use bevy::prelude::*;
 
+use game::AppState;
+
+mod export;
 mod game;
+mod headless;
 mod map;
 mod player;
+mod save;
+mod scores;
+mod settings;
 mod util;
 
 struct MainMenuUI;
 
+struct PauseUI;
+
+struct ResultsUI;
+
+struct SeedText;
+
+struct SeedEntryText;
+
+/// Digits typed on the title screen to choose which island to play.
+#[derive(Default)]
+struct SeedEntry(String);
+
 struct Camera;
 
 fn main() {
+    let windowless_flags = ["--headless", "--dump-map", "--export-png"];
+    if std::env::args().any(|arg| windowless_flags.contains(&arg.as_str())) {
+        headless::run();
+        return;
+    }
+
     App::new()
         .insert_resource(WindowDescriptor {
             title: String::from("I Want to Go Home"),
@@ -22,17 +48,37 @@ fn main() {
         })
         .insert_resource(ClearColor(Color::rgb(26. / 255., 28. / 255., 44. / 255.)))
         .init_resource::<game::Game>()
+        .init_resource::<SeedEntry>()
         .add_plugins(DefaultPlugins)
         .add_plugin(LogDiagnosticsPlugin::default())
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
+        .add_plugin(settings::SettingsPlugin)
         .add_plugin(game::GamePlugin)
         .add_plugin(map::MapPlugin)
         .add_plugin(player::PlayerPlugin)
+        .add_plugin(scores::ScoresPlugin)
+        .add_plugin(save::SavePlugin)
         .add_startup_system(setup_camera)
         .add_startup_system(setup_music)
-        .add_system(pan_camera)
-        .add_startup_system(setup_menu)
-        .add_system(handle_menu_input)
+        .add_startup_system(setup_hud)
+        .add_system(update_seed_text)
+        .add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(setup_menu))
+        .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(handle_menu_input))
+        .add_system_set(
+            SystemSet::on_exit(AppState::MainMenu).with_system(despawn_all::<MainMenuUI>),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(pan_camera)
+                .with_system(handle_pause_input)
+                .with_system(handle_regenerate_input),
+        )
+        .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(setup_pause_menu))
+        .add_system_set(SystemSet::on_update(AppState::Paused).with_system(handle_pause_input))
+        .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(despawn_all::<PauseUI>))
+        .add_system_set(SystemSet::on_enter(AppState::Won).with_system(setup_results))
+        .add_system_set(SystemSet::on_update(AppState::Won).with_system(handle_regenerate_input))
+        .add_system_set(SystemSet::on_exit(AppState::Won).with_system(despawn_all::<ResultsUI>))
         .run();
 }
 
@@ -46,8 +92,58 @@ fn setup_menu(
     mut commands: Commands,
     mut materials: ResMut<Assets<ColorMaterial>>,
     asset_server: Res<AssetServer>,
+    seed_entry: Res<SeedEntry>,
+    map_seed: Res<map::MapSeed>,
+    difficulty: Res<game::Difficulty>,
+    map_config: Res<map::MapConfig>,
+    sight_radius: Res<map::SightRadius>,
+    high_scores: Res<scores::HighScores>,
 ) {
-    commands.spawn_bundle(UiCameraBundle::default());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(45.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        menu_seed_text(
+                            &seed_entry,
+                            &map_seed,
+                            *difficulty,
+                            &map_config,
+                            &sight_radius,
+                            &high_scores,
+                        ),
+                        TextStyle {
+                            font_size: 20.0,
+                            color: Color::rgb(177. / 255., 62. / 255., 83. / 255.),
+                            font: asset_server.load("FiraSans-Bold.ttf"),
+                        },
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            vertical: VerticalAlign::Center,
+                            ..Default::default()
+                        },
+                    ),
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(SeedEntryText)
+                .insert(MainMenuUI);
+        })
+        .insert(MainMenuUI);
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -125,24 +221,377 @@ fn setup_menu(
         .insert(MainMenuUI);
 }
 
-fn handle_menu_input(
+fn setup_hud(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    seed: Res<map::MapSeed>,
+    game: Res<game::Game>,
+    campaign: Res<game::Campaign>,
+) {
+    commands
+        .spawn_bundle(TextBundle {
+            text: Text::with_section(
+                seed_text(&seed, &game, &campaign),
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::rgb(177. / 255., 62. / 255., 83. / 255.),
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                },
+                Default::default(),
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(SeedText);
+}
+
+fn update_seed_text(
+    seed: Res<map::MapSeed>,
+    game: Res<game::Game>,
+    campaign: Res<game::Campaign>,
+    mut query: Query<&mut Text, With<SeedText>>,
+) {
+    if seed.is_changed() {
+        for mut text in query.iter_mut() {
+            text.sections[0].value = seed_text(&seed, &game, &campaign);
+        }
+    }
+}
+
+fn seed_text(seed: &map::MapSeed, game: &game::Game, campaign: &game::Campaign) -> String {
+    if campaign.is_campaign() {
+        format!(
+            "Island {}/{}    Seed: {}",
+            game.level, campaign.levels, seed.0
+        )
+    } else {
+        format!("Seed: {}", seed.0)
+    }
+}
+
+fn handle_menu_input(
     mut key_event_reader: EventReader<KeyboardInput>,
     mut mouse_event_reader: EventReader<MouseButtonInput>,
-    main_menu_ui_query: Query<Entity, With<MainMenuUI>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut mouse_buttons: ResMut<Input<MouseButton>>,
+    mut seed_entry: ResMut<SeedEntry>,
+    mut seed_entry_text_query: Query<&mut Text, With<SeedEntryText>>,
+    mut map_seed: ResMut<map::MapSeed>,
+    difficulty: Res<game::Difficulty>,
+    map_config: Res<map::MapConfig>,
+    sight_radius: Res<map::SightRadius>,
+    high_scores: Res<scores::HighScores>,
+    mut regenerate_events: EventWriter<map::RegenerateMap>,
+    mut resume_events: EventWriter<save::ResumeGame>,
+    mut state: ResMut<State<AppState>>,
 ) {
-    let mut button_pressed = false;
-    if let Some(_) = mouse_event_reader.iter().last() {
-        button_pressed = true;
+    let mut button_pressed = mouse_event_reader.iter().any(|ev| ev.state.is_pressed());
+    let mut resuming = false;
+    let mut load_error = None;
+    for ev in key_event_reader.iter().filter(|ev| ev.state.is_pressed()) {
+        if ev.key_code == Some(KeyCode::Back) {
+            seed_entry.0.pop();
+        } else if ev.key_code == Some(KeyCode::L) {
+            match save::SaveGame::load() {
+                Ok(save) => {
+                    resume_events.send(save::ResumeGame(save));
+                    resuming = true;
+                }
+                Err(err) => {
+                    warn!("{}", err);
+                    load_error = Some(err);
+                }
+            }
+        } else if let Some(digit) = ev.key_code.and_then(seed_digit) {
+            let entry = format!("{}{}", seed_entry.0, digit);
+            if entry.parse::<u64>().is_ok() {
+                seed_entry.0 = entry;
+            }
+        } else {
+            button_pressed = true;
+        }
     }
-    if let Some(_) = key_event_reader.iter().last() {
-        button_pressed = true;
+
+    if seed_entry.is_changed() || load_error.is_some() {
+        let mut menu_text = menu_seed_text(
+            &seed_entry,
+            &map_seed,
+            *difficulty,
+            &map_config,
+            &sight_radius,
+            &high_scores,
+        );
+        if let Some(err) = load_error {
+            menu_text = format!("{}\n{}", menu_text, err);
+        }
+        for mut text in seed_entry_text_query.iter_mut() {
+            text.sections[0].value = menu_text.clone();
+        }
     }
-    if button_pressed {
-        for menu_entity in main_menu_ui_query.iter() {
-            commands.entity(menu_entity).despawn();
+
+    // A resumed save decides the island by itself.
+    if button_pressed && !resuming {
+        if let Ok(seed) = seed_entry.0.parse() {
+            if seed != map_seed.0 {
+                map_seed.0 = seed;
+                regenerate_events.send(map::RegenerateMap::Restart);
+            }
         }
     }
+
+    if button_pressed || resuming {
+        // Don't let the press that dismissed the menu also move the player.
+        keyboard_input.clear();
+        mouse_buttons.clear();
+        state.set(AppState::Playing).unwrap();
+    }
+}
+
+fn seed_digit(key: KeyCode) -> Option<char> {
+    let digit = match key {
+        KeyCode::Key0 | KeyCode::Numpad0 => 0,
+        KeyCode::Key1 | KeyCode::Numpad1 => 1,
+        KeyCode::Key2 | KeyCode::Numpad2 => 2,
+        KeyCode::Key3 | KeyCode::Numpad3 => 3,
+        KeyCode::Key4 | KeyCode::Numpad4 => 4,
+        KeyCode::Key5 | KeyCode::Numpad5 => 5,
+        KeyCode::Key6 | KeyCode::Numpad6 => 6,
+        KeyCode::Key7 | KeyCode::Numpad7 => 7,
+        KeyCode::Key8 | KeyCode::Numpad8 => 8,
+        KeyCode::Key9 | KeyCode::Numpad9 => 9,
+        _ => return None,
+    };
+    std::char::from_digit(digit, 10)
+}
+
+fn menu_seed_text(
+    seed_entry: &SeedEntry,
+    map_seed: &map::MapSeed,
+    difficulty: game::Difficulty,
+    map_config: &map::MapConfig,
+    sight_radius: &map::SightRadius,
+    high_scores: &scores::HighScores,
+) -> String {
+    let prompt = if seed_entry.0.is_empty() {
+        String::from("Type a seed to play a specific island, or press any key to start")
+    } else {
+        format!("Seed: {}", seed_entry.0)
+    };
+    let seed = seed_entry.0.parse().unwrap_or(map_seed.0);
+    let key = scores::ScoreKey::new(seed, difficulty, map_config, sight_radius);
+
+    let mut text = format!("{}\n{}", prompt, best_score_text(high_scores.get(&key)));
+    if save::SaveGame::exists() {
+        text.push_str("\nPress L to resume your saved island");
+    }
+    text
+}
+
+fn best_score_text(best: Option<scores::HighScore>) -> String {
+    match best {
+        Some(best) => format!(
+            "Best on this island: {} moves, {}",
+            best.moves,
+            game::format_time(best.time)
+        ),
+        None => String::from("No best on this island yet"),
+    }
+}
+
+fn handle_pause_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        let next = match state.current() {
+            AppState::Paused => AppState::Playing,
+            _ => AppState::Paused,
+        };
+        // Reaching the exit on the same frame takes priority over pausing.
+        let _ = state.set(next);
+    }
+}
+
+fn handle_regenerate_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    campaign: Res<game::Campaign>,
+    mut game: ResMut<game::Game>,
+    mut regenerate_events: EventWriter<map::RegenerateMap>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let mut request = if keyboard_input.just_pressed(KeyCode::R) {
+        map::RegenerateMap::Restart
+    } else if keyboard_input.just_pressed(KeyCode::N) {
+        map::RegenerateMap::NextIsland
+    } else {
+        return;
+    };
+
+    if *state.current() == AppState::Won {
+        // A finished campaign always starts over on a new first island.
+        if campaign.is_campaign() {
+            request = map::RegenerateMap::NextIsland;
+        }
+        *game = game::Game::default();
+        state.set(AppState::Playing).unwrap();
+    }
+    regenerate_events.send(request);
+}
+
+fn setup_pause_menu(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.5).into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "PAUSED",
+                    TextStyle {
+                        font_size: 50.0,
+                        color: Color::rgb(177. / 255., 62. / 255., 83. / 255.),
+                        font: asset_server.load("FiraSans-Bold.ttf"),
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        vertical: VerticalAlign::Center,
+                        ..Default::default()
+                    },
+                ),
+                ..Default::default()
+            });
+        })
+        .insert(PauseUI);
+}
+
+fn setup_results(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>,
+    game: Res<game::Game>,
+    campaign: Res<game::Campaign>,
+    map_seed: Res<map::MapSeed>,
+    difficulty: Res<game::Difficulty>,
+    map_config: Res<map::MapConfig>,
+    sight_radius: Res<map::SightRadius>,
+    high_scores: Res<scores::HighScores>,
+) {
+    let font = asset_server.load("FiraSans-Bold.ttf");
+
+    let mut lines = vec![
+        (String::from("YOU DID IT"), 50.0),
+        (
+            format!(
+                "Moves: {} (best possible: {})",
+                game.moves, game.exit_distance
+            ),
+            20.0,
+        ),
+        (format!("Time: {}", game::format_time(game.time)), 20.0),
+        (
+            format!(
+                "Explored: {} of {} tiles",
+                game.tiles_revealed, game.walkable_tiles
+            ),
+            20.0,
+        ),
+        (
+            format!("Efficiency: {:.0}%", game.efficiency() * 100.0),
+            30.0,
+        ),
+    ];
+    if campaign.is_campaign() {
+        lines.push((
+            format!(
+                "Campaign: {} islands, {} moves, {}",
+                campaign.levels,
+                game.total_moves,
+                game::format_time(game.total_time)
+            ),
+            20.0,
+        ));
+        lines.push((String::from("R or N: new campaign"), 20.0));
+    } else {
+        // The score may or may not have been recorded yet, so include this run either way.
+        let key = scores::ScoreKey::new(map_seed.0, *difficulty, &map_config, &sight_radius);
+        let best = scores::HighScore::best_of(high_scores.get(&key), game.moves, game.time);
+        lines.push((best_score_text(Some(best)), 20.0));
+        lines.push((String::from("R: this island again    N: next island"), 20.0));
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        padding: Rect::all(Val::Px(20.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.0, 0.0, 0.0, 0.75).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (line, font_size) in lines {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                line,
+                                TextStyle {
+                                    font_size,
+                                    color: Color::rgb(177. / 255., 62. / 255., 83. / 255.),
+                                    font: font.clone(),
+                                },
+                                Default::default(),
+                            ),
+                            style: Style {
+                                margin: Rect::all(Val::Px(5.0)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        });
+                    }
+                });
+        })
+        .insert(ResultsUI);
+}
+
+fn despawn_all<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
 fn setup_camera(mut commands: Commands, mut windows: ResMut<Windows>) {
@@ -150,6 +599,7 @@ fn setup_camera(mut commands: Commands, mut windows: ResMut<Windows>) {
         .get_primary_mut()
         .unwrap()
         .update_scale_factor_from_backend(1.0);
+    commands.spawn_bundle(UiCameraBundle::default());
     commands
         .spawn_bundle(OrthographicCameraBundle {
             orthographic_projection: bevy::render::camera::OrthographicProjection {
@@ -165,28 +615,23 @@ fn pan_camera(
     time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
     mut query: Query<&mut Transform, With<Camera>>,
-    game: Res<game::Game>,
 ) {
-    if game.won {
-        return;
-    }
-
     for mut transform in query.iter_mut() {
         let mut direction = Vec3::ZERO;
 
-        if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left) {
+        if keyboard_input.pressed(KeyCode::Left) {
             direction -= Vec3::new(1.0, 0.0, 0.0);
         }
 
-        if keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right) {
+        if keyboard_input.pressed(KeyCode::Right) {
             direction += Vec3::new(1.0, 0.0, 0.0);
         }
 
-        if keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up) {
+        if keyboard_input.pressed(KeyCode::Up) {
             direction += Vec3::new(0.0, 1.0, 0.0);
         }
 
-        if keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down) {
+        if keyboard_input.pressed(KeyCode::Down) {
             direction -= Vec3::new(0.0, 1.0, 0.0);
         }
 
@@ -1,25 +1,106 @@
 use bevy::prelude::*;
-use noise::{NoiseFn, OpenSimplex, Seedable};
+use bevy::utils::HashMap;
 use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::process;
 
+pub use i_want_to_go_home::core::map::*;
+pub use i_want_to_go_home::core::{Island, MoveOutcome};
+
+use super::game::*;
 use super::player::*;
+use super::settings::Settings;
 use super::util::*;
 use super::Camera;
 
-pub enum TileType {
-    Water,
-    Grass,
-    Exit,
+pub struct Tile {
+    pub hex: HexCoord,
 }
 
-pub struct Tile {
-    pub hex: Hex,
-    pub tile_type: TileType,
+/// Seed that drives all random choices made while generating a map, so that the same seed always
+/// produces the same island, spawn tile and exit tile.
+pub struct MapSeed(pub u64);
+
+impl MapSeed {
+    pub fn random() -> Self {
+        MapSeed(rand::thread_rng().gen::<u32>() as u64)
+    }
+}
+
+impl FromWorld for MapSeed {
+    fn from_world(world: &mut World) -> Self {
+        world
+            .get_resource::<Settings>()
+            .and_then(|settings| settings.seed)
+            .map_or_else(MapSeed::random, MapSeed)
+    }
+}
+
+/// Asks for the current island to be thrown away and generated again at the end of the frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegenerateMap {
+    /// The same island again, from the current seed.
+    Restart,
+    /// A different island, from a new random seed.
+    NextIsland,
+    /// The island in the `RestoredIsland` resource, e.g. from a save file.
+    Restore,
+}
+
+/// An island in progress to set up instead of generating one from the seed.
+pub struct RestoredIsland {
+    pub island: Island,
+    pub moves: u32,
+    pub time: f32,
+}
+
+/// Set while a regenerated map is waiting to be set up.
+#[derive(Default)]
+struct PendingMap(bool);
+
+/// How many tiles away the player can see, as long as nothing blocks the line of sight.
+pub struct SightRadius(pub u32);
+
+impl FromWorld for SightRadius {
+    fn from_world(world: &mut World) -> Self {
+        let default_settings = Settings::default();
+        let settings = world
+            .get_resource::<Settings>()
+            .unwrap_or(&default_settings);
+
+        SightRadius(settings.sight_radius)
+    }
 }
 
-pub struct Walkable;
+/// Tile entities by coordinate, for looking tiles up without scanning every tile.
+#[derive(Default)]
+pub struct TileIndex {
+    tiles: HashMap<HexCoord, Entity>,
+    coords: HashMap<Entity, HexCoord>,
+}
 
-pub struct Exit;
+impl TileIndex {
+    pub fn entity(&self, hex: HexCoord) -> Option<Entity> {
+        self.tiles.get(&hex).copied()
+    }
+
+    pub fn insert(&mut self, hex: HexCoord, entity: Entity) {
+        if let Some(old_hex) = self.coords.insert(entity, hex) {
+            if old_hex != hex {
+                self.tiles.remove(&old_hex);
+            }
+        }
+        self.tiles.insert(hex, entity);
+    }
+
+    pub fn remove_entity(&mut self, entity: Entity) {
+        if let Some(hex) = self.coords.remove(&entity) {
+            if self.entity(hex) == Some(entity) {
+                self.tiles.remove(&hex);
+            }
+        }
+    }
+}
 
 pub struct MapPlugin;
 
@@ -32,112 +113,240 @@ pub enum MapStage {
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_stage_after(
-            StartupStage::Startup,
-            MapStage::Setup,
-            SystemStage::single_threaded(),
-        )
-        .add_startup_stage_after(
-            MapStage::Setup,
-            MapStage::Populate,
-            SystemStage::single_threaded(),
-        )
-        .add_startup_stage_after(
-            MapStage::Populate,
-            MapStage::Ready,
-            SystemStage::single_threaded(),
-        )
-        .add_startup_system_to_stage(MapStage::Setup, setup_map)
-        .add_startup_system_to_stage(MapStage::Populate, populate_map)
-        .add_startup_system_to_stage(MapStage::Ready, focus_player);
+        let map_config = app
+            .world
+            .get_resource::<Settings>()
+            .map(|settings| settings.map.clone())
+            .unwrap_or_default();
+        let map_config = match map_config.validate() {
+            Ok(()) => map_config,
+            Err(err) => {
+                error!(
+                    "Invalid map configuration, using the defaults instead: {}",
+                    err
+                );
+                MapConfig::default()
+            }
+        };
+
+        let headless = app
+            .world
+            .get_resource::<Settings>()
+            .map_or(false, |settings| settings.windowless());
+
+        app.insert_resource(map_config)
+            .init_resource::<MapSeed>()
+            .init_resource::<SightRadius>()
+            .init_resource::<TileIndex>()
+            .init_resource::<PendingMap>()
+            .add_event::<RegenerateMap>()
+            .add_startup_stage_after(
+                StartupStage::Startup,
+                MapStage::Setup,
+                SystemStage::single_threaded(),
+            )
+            .add_startup_stage_after(
+                MapStage::Setup,
+                MapStage::Populate,
+                SystemStage::single_threaded(),
+            )
+            .add_startup_stage_after(
+                MapStage::Populate,
+                MapStage::Ready,
+                SystemStage::single_threaded(),
+            )
+            .add_startup_system_to_stage(MapStage::Setup, setup_map)
+            .add_stage_after(
+                CoreStage::Update,
+                MapStage::Setup,
+                SystemStage::single_threaded(),
+            )
+            .add_stage_after(
+                MapStage::Setup,
+                MapStage::Populate,
+                SystemStage::single_threaded(),
+            )
+            .add_stage_after(
+                MapStage::Populate,
+                MapStage::Ready,
+                SystemStage::single_threaded(),
+            )
+            .add_system(clear_map)
+            .add_system_to_stage(MapStage::Setup, setup_map.with_run_criteria(map_pending))
+            .add_system_to_stage(CoreStage::Last, finish_map);
+
+        // Without a window there is nothing to draw the island with, so only the `Island` itself
+        // is set up.
+        if !headless {
+            app.add_startup_system_to_stage(MapStage::Populate, populate_map)
+                .add_startup_system_to_stage(MapStage::Ready, focus_player)
+                .add_system_to_stage(
+                    MapStage::Populate,
+                    populate_map.with_run_criteria(map_pending),
+                )
+                .add_system_to_stage(MapStage::Ready, focus_player.with_run_criteria(map_pending))
+                .add_system_to_stage(CoreStage::PostUpdate, sync_tile_index);
+        }
     }
 }
 
-fn setup_map(
+/// Despawns the current map when a `RegenerateMap` is requested, so that the map stages generate a
+/// new one in the same frame.
+fn clear_map(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut regenerate_events: EventReader<RegenerateMap>,
+    mut seed: ResMut<MapSeed>,
+    mut pending_map: ResMut<PendingMap>,
+    mut travel: ResMut<Travel>,
+    map_entity_query: Query<Entity, Or<(With<Tile>, With<Player>, With<PathMarker>)>>,
 ) {
-    let width = 40;
-    let height = 40;
-    let water_level = 0.27;
-    let walkable_tile_threshold = 250;
-
-    loop {
-        let height_map = HeightMap::new(width, height);
-        let mut walkable_tile_count = 0;
-
-        for y in 0..height - 1 {
-            for x in 0..width - 1 {
-                let value = height_map.get(x, y);
-                let mut walkable = false;
-                let tile_type = if value < water_level {
-                    TileType::Water
-                } else {
-                    walkable = true;
-                    TileType::Grass
-                };
-                let texture_handle = texture_handle_for_tile_type(&asset_server, &tile_type);
-
-                let q = x as f32;
-                let r = y as f32 - (x as f32 / 2.0).floor();
-                let hex = Hex::new(q, r);
-                let pixel_coords = hex.to_pixel_coords();
-
-                let entity = Some(
-                    commands
-                        .spawn_bundle(SpriteBundle {
-                            material: materials.add(texture_handle.into()),
-                            transform: Transform::from_translation(Vec3::new(
-                                pixel_coords.x,
-                                pixel_coords.y,
-                                0.0,
-                            )),
-                            visible: Visible {
-                                is_visible: false,
-                                is_transparent: true,
-                            },
-                            ..Default::default()
-                        })
-                        .insert(Tile { hex, tile_type })
-                        .id(),
-                );
+    let request = match regenerate_events.iter().last() {
+        Some(request) => *request,
+        None => return,
+    };
 
-                if walkable {
-                    commands.entity(entity.unwrap()).insert(Walkable {});
-                    walkable_tile_count += 1;
+    if request == RegenerateMap::NextIsland {
+        *seed = MapSeed::random();
+    }
+
+    for entity in map_entity_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    travel.path.clear();
+    pending_map.0 = true;
+}
+
+/// Marks the map as set up once every map stage has run for it.
+fn finish_map(mut pending_map: ResMut<PendingMap>) {
+    pending_map.0 = false;
+}
+
+fn map_pending(pending_map: Res<PendingMap>) -> ShouldRun {
+    if pending_map.0 {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn setup_map(
+    mut commands: Commands,
+    seed: Res<MapSeed>,
+    map_config: Res<MapConfig>,
+    difficulty: Res<Difficulty>,
+    sight_radius: Res<SightRadius>,
+    restored: Option<Res<RestoredIsland>>,
+    mut game: ResMut<Game>,
+) {
+    let island = match &restored {
+        Some(restored) => restored.island.clone(),
+        None => {
+            let config = map_config.for_level(game.level);
+            let mut rng = StdRng::seed_from_u64(seed.0);
+            let generated = match generate_or_fallback(&config, &mut rng, log_attempt) {
+                Some(generated) => generated,
+                None => {
+                    error!("Could not generate an island with any map configuration, giving up");
+                    process::exit(1);
                 }
-            }
+            };
+
+            Island::place(generated, *difficulty, sight_radius.0, &mut rng)
         }
+    };
 
-        if walkable_tile_count >= walkable_tile_threshold {
-            break;
+    game.start_island(island.grid().walkable_count());
+    game.tiles_revealed = island.explored();
+    game.exit_distance = island.exit_distance();
+    if let Some(restored) = &restored {
+        game.moves = restored.moves;
+        game.time = restored.time;
+        commands.remove_resource::<RestoredIsland>();
+    }
+
+    commands.insert_resource(island);
+}
+
+/// Generates an island for `config`. If none of the attempts reaches its walkable tile threshold,
+/// the largest landmass found is played instead, and if there was none, the default configuration
+/// is tried once. Every attempt is passed to `on_attempt`.
+fn generate_or_fallback(
+    config: &MapConfig,
+    rng: &mut StdRng,
+    mut on_attempt: impl FnMut(&GenerationAttempt, &MapConfig),
+) -> Option<GeneratedIsland> {
+    let err = match generate_island(config, rng, |attempt| on_attempt(attempt, config)) {
+        Ok(generated) => return Some(generated),
+        Err(err) => err,
+    };
+    if err.best.is_some() {
+        error!("{}; playing the largest landmass found instead", err);
+        return err.best;
+    }
+    error!("{}; trying the default map configuration instead", err);
+
+    let default = MapConfig::default();
+    match generate_island(&default, rng, |attempt| on_attempt(attempt, &default)) {
+        Ok(generated) => Some(generated),
+        Err(err) => {
+            if err.best.is_some() {
+                error!("{}; playing the largest landmass found instead", err);
+            }
+            err.best
         }
     }
 }
 
+fn log_attempt(attempt: &GenerationAttempt, config: &MapConfig) {
+    debug!(
+        "Island attempt {}: {:.1}% walkable, {} landmasses, largest has {} of {} tiles needed",
+        attempt.attempt,
+        100.0 * attempt.walkable_ratio,
+        attempt.landmasses,
+        attempt.largest_landmass,
+        config.walkable_tile_threshold
+    );
+}
+
 fn populate_map(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    tile_query: Query<&Tile>,
+    island: Res<Island>,
 ) {
-    let walkable_tiles: Vec<&Tile> = tile_query
-        .iter()
-        .map(|tile| &(*tile))
-        .filter(|tile| {
-            if let TileType::Grass = tile.tile_type {
-                true
-            } else {
-                false
-            }
-        })
-        .collect();
+    let mut tile_index = TileIndex::default();
+
+    let grid = island.grid();
+    for (x, y) in grid.positions() {
+        let hex = grid.hex(x, y);
+        let tile_type = island
+            .tile_type(hex)
+            .expect("Every grid position should have a tile.");
+        let pixel_coords = hex.to_pixel_coords();
+
+        let entity = commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(material_for_tile_type(&asset_server, &tile_type)),
+                transform: Transform::from_translation(Vec3::new(
+                    pixel_coords.x,
+                    pixel_coords.y,
+                    0.0,
+                )),
+                visible: Visible {
+                    is_visible: false,
+                    is_transparent: true,
+                },
+                ..Default::default()
+            })
+            .insert(Tile { hex })
+            .id();
+
+        tile_index.insert(hex, entity);
+    }
+
+    commands.insert_resource(tile_index);
 
-    let mut rng = rand::thread_rng();
-    let spawn_tile_index = rng.gen_range(0..walkable_tiles.len());
-    let spawn_tile = walkable_tiles.get(spawn_tile_index).unwrap();
-    let player_coords = spawn_tile.hex.to_pixel_coords();
+    let player_coords = island.player().to_pixel_coords();
 
     commands
         .spawn_bundle(SpriteBundle {
@@ -150,40 +359,14 @@ fn populate_map(
             ..Default::default()
         })
         .insert(Player);
-
-    let mut exit_tile_index;
-    loop {
-        exit_tile_index = rng.gen_range(0..walkable_tiles.len());
-        if exit_tile_index != spawn_tile_index {
-            break;
-        }
-    }
-
-    let exit_tile = walkable_tiles.get(exit_tile_index).unwrap();
-    let exit_coords = exit_tile.hex.to_pixel_coords();
-
-    commands
-        .spawn_bundle(SpriteBundle {
-            material: materials
-                .add(texture_handle_for_tile_type(&asset_server, &TileType::Exit).into()),
-            transform: Transform::from_translation(Vec3::new(exit_coords.x, exit_coords.y, 5.0)),
-            visible: Visible {
-                is_visible: false,
-                is_transparent: true,
-            },
-            ..Default::default()
-        })
-        .insert(Tile {
-            tile_type: TileType::Exit,
-            hex: exit_tile.hex.clone(),
-        })
-        .insert(Exit);
 }
 
 fn focus_player(
     player_query: Query<&Transform, (With<Player>, Without<Camera>)>,
     mut camera_query: Query<&mut Transform, With<Camera>>,
-    mut tile_query: Query<(&Tile, &mut Visible)>,
+    tile_index: Res<TileIndex>,
+    island: Res<Island>,
+    mut visible_query: Query<&mut Visible, With<Tile>>,
 ) {
     let player_transform = player_query
         .single()
@@ -193,107 +376,95 @@ fn focus_player(
         .single_mut()
         .expect("There should only be one camera.");
 
-    let spawn_tile = Hex::from_pixel_coords(&Vec2::from(player_transform.translation)).rounded();
-
-    tile_query
-        .iter_mut()
-        .filter(|(tile, _)| tile.hex.distance_to(&spawn_tile) <= 1)
-        .for_each(|(_, mut visible)| visible.is_visible = true);
+    show_tiles(
+        island.revealed().iter().copied(),
+        &tile_index,
+        &mut visible_query,
+    );
 
     camera_transform.translation.x = player_transform.translation.x;
     camera_transform.translation.y = player_transform.translation.y;
 }
 
-fn texture_handle_for_tile_type(
-    asset_server: &AssetServer,
-    tile_type: &TileType,
-) -> Handle<Texture> {
-    let texture_handle = match tile_type {
-        TileType::Water => asset_server.load("water.png"),
-        TileType::Grass => asset_server.load("grass.png"),
-        TileType::Exit => asset_server.load("exit.png"),
-    };
-
-    texture_handle
-}
-
-pub struct HeightMap {
-    noise: OpenSimplex,
-    width: u32,
-    height: u32,
-    octave_scale: f64,
-    octave_count: usize,
-    octave_persistence: f64,
-    map: Vec<f64>,
+/// Makes the sprites of the tiles at `hexes` visible, e.g. once the player has seen them.
+pub fn show_tiles(
+    hexes: impl IntoIterator<Item = HexCoord>,
+    tile_index: &TileIndex,
+    visible_query: &mut Query<&mut Visible, With<Tile>>,
+) {
+    for hex in hexes {
+        if let Some(entity) = tile_index.entity(hex) {
+            if let Ok(mut visible) = visible_query.get_mut(entity) {
+                visible.is_visible = true;
+            }
+        }
+    }
 }
 
-impl HeightMap {
-    fn new(width: u32, height: u32) -> Self {
-        let mut rng = rand::thread_rng();
-        let seed: u32 = rng.gen::<u32>();
-        let noise = OpenSimplex::new().set_seed(seed);
-        let mut height_map = Self {
-            noise,
-            width,
-            height,
-            octave_scale: 0.025,
-            octave_count: 8,
-            octave_persistence: 0.015,
-            map: vec![0.0; width as usize * height as usize],
-        };
-
-        height_map.generate();
-
-        height_map
+fn sync_tile_index(
+    mut tile_index: ResMut<TileIndex>,
+    changed_tile_query: Query<(Entity, &Tile), Changed<Tile>>,
+    removed_tiles: RemovedComponents<Tile>,
+) {
+    for entity in removed_tiles.iter() {
+        tile_index.remove_entity(entity);
     }
 
-    fn generate(&mut self) {
-        for y in 0..self.height - 1 {
-            for x in 0..self.width - 1 {
-                let index = (y * self.width + x) as usize;
-                self.map[index] = self.sum_octave(x, y) * self.get_base_value(x, y);
-            }
-        }
+    for (entity, tile) in changed_tile_query.iter() {
+        tile_index.insert(tile.hex, entity);
     }
+}
 
-    fn get(&self, x: u32, y: u32) -> f64 {
-        let index = (y * self.width + x) as usize;
-        self.map[index]
+/// The sprite tiles of a type are drawn with, and the color it is tinted with.
+pub fn sprite_for_tile_type(tile_type: TileType) -> (&'static str, Color) {
+    match tile_type {
+        TileType::DeepWater => ("water.png", Color::rgb(0.55, 0.6, 0.85)),
+        TileType::Shallows => ("water.png", Color::WHITE),
+        TileType::River => ("water.png", Color::rgb(0.7, 0.9, 1.0)),
+        TileType::Ford => ("water.png", Color::rgb(0.9, 0.85, 0.65)),
+        TileType::Sand => ("sand.png", Color::WHITE),
+        TileType::Grass => ("grass.png", Color::WHITE),
+        TileType::Forest => ("forest.png", Color::WHITE),
+        TileType::Mountain => ("mountain.png", Color::WHITE),
+        TileType::Exit => ("exit.png", Color::WHITE),
     }
+}
 
-    fn get_base_value(&self, x: u32, y: u32) -> f64 {
-        let center_x = self.width as f64 / 2.0;
-        let center_y = self.height as f64 / 2.0;
-
-        let max_dist = (center_x * center_y + center_y * center_y).sqrt();
+fn material_for_tile_type(asset_server: &AssetServer, tile_type: &TileType) -> ColorMaterial {
+    let (sprite, color) = sprite_for_tile_type(*tile_type);
+    ColorMaterial::modulated_texture(asset_server.load(sprite), color)
+}
 
-        let dist_x = center_x - x as f64;
-        let dist_y = center_y - y as f64;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        return 1.0 - (dist_x * dist_x + dist_y * dist_y).sqrt() / max_dist;
-    }
+    #[test]
+    fn test_generate_or_fallback_gives_up_in_bounded_attempts() {
+        // Valid, but more land than the noise ever produces.
+        let config = MapConfig {
+            walkable_tile_threshold: MapConfig::default().max_walkable_tiles(),
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(83721);
+        let mut attempts = 0;
 
-    fn sum_octave(&self, x: u32, y: u32) -> f64 {
-        let noise_min = 0.0;
-        let noise_max = 1.0;
-        let mut max_amp = 0.0;
-        let mut amp = 1.0;
-        let mut freq = self.octave_scale;
-        let mut value = 0.0;
-
-        for _ in 0..self.octave_count {
-            value += self.noise.get([x as f64 * freq, y as f64 * freq]) * amp;
-            max_amp += amp;
-            amp *= self.octave_persistence;
-            freq *= 2.0;
-        }
+        let generated = generate_or_fallback(&config, &mut rng, |_, _| attempts += 1);
 
-        // Take the average noise value of the iterations
-        value /= max_amp;
+        let (_, landmass) = generated.unwrap();
+        assert!(landmass.len() < config.walkable_tile_threshold);
+        assert_eq!(attempts, MAX_GENERATION_ATTEMPTS);
 
-        // Normalize the result
-        value = value * (noise_max - noise_min) / 2.0 + (noise_max + noise_min) / 2.0;
+        // Without any land at all, the default configuration gets one more round of attempts.
+        let flooded = MapConfig {
+            water_level: 2.0,
+            ..config
+        };
+        attempts = 0;
+        let generated = generate_or_fallback(&flooded, &mut rng, |_, _| attempts += 1);
 
-        value
+        assert!(generated.is_some());
+        assert!(attempts > MAX_GENERATION_ATTEMPTS);
+        assert!(attempts <= 2 * MAX_GENERATION_ATTEMPTS);
     }
 }
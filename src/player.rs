@@ -1,8 +1,12 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
 use bevy::prelude::*;
 use bevy::render::camera::OrthographicProjection;
 
 use super::map::*;
 use super::game::*;
+use super::settings::Settings;
 use super::util::*;
 use super::Camera;
 
@@ -10,13 +14,76 @@ pub struct Player;
 
 pub struct Cursor;
 
+/// Marks the sprites previewing the path to the hovered tile.
+pub struct PathMarker;
+
+struct PathMarkerMaterial(Handle<ColorMaterial>);
+
+/// The remaining steps of a click-to-travel walk.
+pub struct Travel {
+    pub path: VecDeque<HexCoord>,
+    /// How long crossing a tile with a movement cost of 1 takes.
+    step_seconds: f32,
+    /// Runs while the player is still crossing the tile they last stepped onto.
+    step_timer: Timer,
+}
+
+impl Travel {
+    fn new(step_seconds: f32) -> Self {
+        Travel {
+            path: VecDeque::new(),
+            step_seconds,
+            step_timer: Timer::from_seconds(step_seconds, false),
+        }
+    }
+}
+
+/// How long crossing a tile with a movement cost of 1 takes when playing in a window.
+const STEP_SECONDS: f32 = 0.15;
+
+/// Asks the player to take one step in a direction, e.g. from the keyboard or a gamepad.
+pub struct StepEvent(pub HexDirection);
+
+/// Sent after the player has stepped onto a tile.
+pub struct PlayerMoved(pub MoveOutcome);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum PlayerSystem {
+    Movement,
+}
+
+/// How far a gamepad stick has to be pushed before it counts as a step.
+const STICK_THRESHOLD: f32 = 0.5;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(cursor_init)
-            .add_system(movement_system)
-            .add_system(cursor_system);
+        let headless = app
+            .world
+            .get_resource::<Settings>()
+            .map_or(false, |settings| settings.windowless());
+        // Scripted steps shouldn't have to wait for the player to walk across the screen.
+        let step_seconds = if headless { 0.0 } else { STEP_SECONDS };
+
+        app.insert_resource(Travel::new(step_seconds))
+            .add_event::<StepEvent>()
+            .add_event::<PlayerMoved>()
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(movement_system.label(PlayerSystem::Movement))
+                    .with_system(exit_system),
+            );
+
+        if !headless {
+            app.add_startup_system(cursor_init).add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(direction_input_system.before(PlayerSystem::Movement))
+                    .with_system(travel_input_system.before(PlayerSystem::Movement))
+                    .with_system(player_sprite_system.after(PlayerSystem::Movement))
+                    .with_system(cursor_system),
+            );
+        }
     }
 }
 
@@ -25,9 +92,13 @@ fn cursor_init(
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
+    let cursor_texture = asset_server.load("cursor.png");
+    commands.insert_resource(PathMarkerMaterial(materials.add(
+        ColorMaterial::modulated_texture(cursor_texture.clone(), Color::rgba(1.0, 1.0, 1.0, 0.5)),
+    )));
     commands
         .spawn_bundle(SpriteBundle {
-            material: materials.add(asset_server.load("cursor.png").into()),
+            material: materials.add(cursor_texture.into()),
             transform: Transform::from_translation(Vec3::new(0.0, 0.0, 20.0)),
             visible: Visible {
                 is_visible: false,
@@ -38,127 +109,238 @@ fn cursor_init(
         .insert(Cursor);
 }
 
-fn movement_system(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
+fn direction_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut last_stick_direction: Local<Option<HexDirection>>,
+    mut step_events: EventWriter<StepEvent>,
+) {
+    let key_directions = [
+        (KeyCode::W, HexDirection::North),
+        (KeyCode::E, HexDirection::NorthEast),
+        (KeyCode::D, HexDirection::SouthEast),
+        (KeyCode::S, HexDirection::South),
+        (KeyCode::A, HexDirection::SouthWest),
+        (KeyCode::Q, HexDirection::NorthWest),
+    ];
+    for (key, direction) in key_directions.iter() {
+        if keyboard_input.just_pressed(*key) {
+            step_events.send(StepEvent(*direction));
+        }
+    }
+
+    let stick_direction = gamepads.iter().find_map(|gamepad| {
+        let x = axes.get(GamepadAxis(*gamepad, GamepadAxisType::LeftStickX))?;
+        let y = axes.get(GamepadAxis(*gamepad, GamepadAxisType::LeftStickY))?;
+        let stick = Vec2::new(x, y);
+        if stick.length() >= STICK_THRESHOLD {
+            Some(HexDirection::nearest(stick.x, stick.y))
+        } else {
+            None
+        }
+    });
+    if stick_direction != *last_stick_direction {
+        if let Some(direction) = stick_direction {
+            step_events.send(StepEvent(direction));
+        }
+        *last_stick_direction = stick_direction;
+    }
+}
+
+/// Starts a click-to-travel walk to the clicked tile, or stops the current one.
+fn travel_input_system(
     windows: Res<Windows>,
     mouse_buttons: Res<Input<MouseButton>>,
-    mut player_query: Query<&mut Transform, (With<Player>, Without<Camera>)>,
     camera_query: Query<(&Transform, &OrthographicProjection), With<Camera>>,
-    walkable_tile_query: Query<&Tile, With<Walkable>>,
-    mut tile_query: Query<(&Tile, &mut Visible)>,
-    exit_tile_query: Query<&Tile, With<Exit>>,
-    mut game: ResMut<Game>,
+    island: Res<Island>,
+    mut travel: ResMut<Travel>,
 ) {
-    if game.won {
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if !travel.path.is_empty() {
+        travel.path.clear();
         return;
     }
 
     let window = windows.get_primary().unwrap();
+    let (cam_transform, cam_projection) = camera_query.single().unwrap();
+    if let Some(mouse_pos) = window.cursor_position() {
+        let mouse_world_pos =
+            window_to_world_coords(&window, &cam_transform, &cam_projection, mouse_pos);
+        let mouse_tile_coords = HexCoord::from_pixel_coords(&mouse_world_pos);
+        if let Some(path) = island.path_to(mouse_tile_coords) {
+            travel.path = path.into_iter().collect();
+        }
+    }
+}
+
+fn movement_system(
+    time: Res<Time>,
+    mut island: ResMut<Island>,
+    mut step_events: EventReader<StepEvent>,
+    mut moved_events: EventWriter<PlayerMoved>,
+    mut travel: ResMut<Travel>,
+    mut game: ResMut<Game>,
+) {
+    // Stepping after the player made it home would fail anyway, but going through `ResMut` would
+    // still mark the island as changed and make `exit_system` handle the escape a second time.
+    if island.has_escaped() {
+        return;
+    }
+
+    // Steps wait until the player has finished crossing the current tile, so slow terrain holds
+    // up walking and keyboard steps alike.
+    let ready = travel.step_timer.tick(time.delta()).finished();
+    let mut next_step = None;
+    if ready {
+        next_step = travel.path.pop_front();
+    }
+
+    if let Some(StepEvent(direction)) = step_events.iter().last() {
+        let neighbor = island.player().neighbor(*direction);
+        if ready && island.is_walkable(neighbor) {
+            travel.path.clear();
+            next_step = Some(neighbor);
+        }
+    }
+
+    let step = match next_step {
+        Some(step) => step,
+        None => return,
+    };
+    let outcome = match island.move_to(step) {
+        Ok(outcome) => outcome,
+        Err(err) => {
+            warn!("Could not step to {:?}: {}", step, err);
+            travel.path.clear();
+            return;
+        }
+    };
+
+    let step_seconds = travel.step_seconds * outcome.cost as f32;
+    travel
+        .step_timer
+        .set_duration(Duration::from_secs_f32(step_seconds));
+    travel.step_timer.reset();
+
+    game.moves += 1;
+    game.total_moves += 1;
+    game.tiles_revealed = island.explored();
+
+    moved_events.send(PlayerMoved(outcome));
+}
+
+/// Moves the player's sprite to where they stepped, and shows the tiles they saw on the way.
+fn player_sprite_system(
+    mut moved_events: EventReader<PlayerMoved>,
+    island: Res<Island>,
+    tile_index: Res<TileIndex>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<Camera>)>,
+    mut visible_query: Query<&mut Visible, With<Tile>>,
+) {
+    let mut moved = false;
+    for PlayerMoved(outcome) in moved_events.iter() {
+        show_tiles(
+            outcome.revealed.iter().copied(),
+            &tile_index,
+            &mut visible_query,
+        );
+        moved = true;
+    }
+    if !moved {
+        return;
+    }
+
     let mut player_transform = player_query
         .single_mut()
         .expect("There should only be one player.");
+    let player_dest = island.player().to_pixel_coords();
+    player_transform.translation.x = player_dest.x;
+    player_transform.translation.y = player_dest.y;
+}
 
-    let exit_tile = exit_tile_query
-        .single()
-        .expect("There should only be one exit tile.");
+/// Moves on to the next island of a campaign when the player reaches the exit, or ends the run on
+/// the last one.
+fn exit_system(
+    island: Res<Island>,
+    campaign: Res<Campaign>,
+    mut game: ResMut<Game>,
+    mut travel: ResMut<Travel>,
+    mut regenerate_events: EventWriter<RegenerateMap>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !island.is_changed() || !island.has_escaped() {
+        return;
+    }
 
-    if mouse_buttons.just_pressed(MouseButton::Left) {
-        if let Some(mouse_pos) = window.cursor_position() {
-            let (cam_transform, cam_projection) = camera_query.single().unwrap();
-            let mouse_world_pos =
-                window_to_world_coords(&window, &cam_transform, &cam_projection, mouse_pos);
-
-            let cur_player_coords =
-                Hex::from_pixel_coords(&Vec2::from(player_transform.translation));
-            let mouse_tile_coords = Hex::from_pixel_coords(&mouse_world_pos);
-            let dest_tile = walkable_tile_query
-                .iter()
-                .find(|t| t.hex == mouse_tile_coords);
-
-            if let Some(dt) = dest_tile {
-                if cur_player_coords.distance_to(&mouse_tile_coords) == 1 {
-                    let player_dest = mouse_tile_coords.to_pixel_coords();
-                    player_transform.translation.x = player_dest.x;
-                    player_transform.translation.y = player_dest.y;
-
-                    tile_query
-                        .iter_mut()
-                        .filter(|(tile, _)| tile.hex.distance_to(&dt.hex) <= 1)
-                        .for_each(|(_, mut visible)| visible.is_visible = true);
-
-                    if mouse_tile_coords == exit_tile.hex {
-                        game.won = true;
-                        commands.spawn_bundle(Text2dBundle {
-                            text: Text::with_section(
-                                "YOU DID IT",
-                                TextStyle {
-                                    font_size: 50.0,
-                                    color: Color::rgb(177. / 255., 62. / 255., 83. / 255.),
-                                    font: asset_server.load("FiraSans-Bold.ttf"),
-                                },
-                                TextAlignment {
-                                    horizontal: HorizontalAlign::Center,
-                                    ..Default::default()
-                                },
-                            ),
-                            transform: Transform::from_translation(Vec3::new(
-                                cam_transform.translation.x,
-                                cam_transform.translation.y,
-                                100.0,
-                            )),
-                            ..Default::default()
-                        });
-                    }
-                }
-            }
-        }
+    travel.path.clear();
+    if campaign.is_last_level(&game) {
+        state.overwrite_set(AppState::Won).unwrap();
+    } else {
+        game.level += 1;
+        regenerate_events.send(RegenerateMap::NextIsland);
     }
 }
 
 fn cursor_system(
+    mut commands: Commands,
     windows: Res<Windows>,
     camera_query: Query<(&Transform, &OrthographicProjection), With<Camera>>,
-    player_query: Query<&Transform, (With<Player>, Without<Camera>)>,
-    mut cursor_query: Query<
-        (&mut Transform, &mut Visible),
-        (With<Cursor>, Without<Camera>, Without<Player>),
-    >,
+    mut cursor_query: Query<(&mut Transform, &mut Visible), (With<Cursor>, Without<Camera>)>,
+    marker_query: Query<Entity, With<PathMarker>>,
+    marker_material: Res<PathMarkerMaterial>,
     mut cursor_event_reader: EventReader<CursorMoved>,
     mouse_buttons: Res<Input<MouseButton>>,
-    tile_query: Query<&Tile, With<Walkable>>,
+    island: Res<Island>,
+    travel: Res<Travel>,
 ) {
     let (mut cursor_transform, mut cursor_visible) = cursor_query
         .single_mut()
         .expect("There should only be one cursor.");
     let window = windows.get_primary().unwrap();
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        cursor_visible.is_visible = false;
+        for marker in marker_query.iter() {
+            commands.entity(marker).despawn();
+        }
+        return;
+    }
+
     if let Some(ev) = cursor_event_reader.iter().last() {
+        for marker in marker_query.iter() {
+            commands.entity(marker).despawn();
+        }
+        cursor_visible.is_visible = false;
+        if !travel.path.is_empty() {
+            return;
+        }
+
         let mouse_pos = ev.position;
         let (cam_transform, cam_projection) = camera_query.single().unwrap();
         let mouse_world_pos =
             window_to_world_coords(&window, &cam_transform, &cam_projection, mouse_pos);
-        let mouse_tile_coords = Hex::from_pixel_coords(&mouse_world_pos);
-        let dest_tile = tile_query.iter().find(|t| t.hex == mouse_tile_coords);
-
-        if let Some(dt) = dest_tile {
-            let player_transform = player_query
-                .single()
-                .expect("There should only be one player.");
-            let cur_player_coords =
-                Hex::from_pixel_coords(&Vec2::from(player_transform.translation));
-            if dt.hex.distance_to(&cur_player_coords) == 1 {
-                let dest = dt.hex.to_pixel_coords();
-                cursor_transform.translation.x = dest.x;
-                cursor_transform.translation.y = dest.y;
-                cursor_visible.is_visible = true;
-                return;
+        let mouse_tile_coords = HexCoord::from_pixel_coords(&mouse_world_pos);
+
+        if let Some(path) = island.path_to(mouse_tile_coords) {
+            let dest = mouse_tile_coords.to_pixel_coords();
+            cursor_transform.translation.x = dest.x;
+            cursor_transform.translation.y = dest.y;
+            cursor_visible.is_visible = true;
+
+            for hex in &path[..path.len() - 1] {
+                let pos = hex.to_pixel_coords();
+                commands
+                    .spawn_bundle(SpriteBundle {
+                        material: marker_material.0.clone(),
+                        transform: Transform::from_translation(Vec3::new(pos.x, pos.y, 15.0)),
+                        ..Default::default()
+                    })
+                    .insert(PathMarker);
             }
         }
-        cursor_visible.is_visible = false;
-    }
-
-    if mouse_buttons.just_pressed(MouseButton::Left) {
-        cursor_visible.is_visible = false;
     }
 }
 
@@ -0,0 +1,325 @@
+use bevy::prelude::*;
+use bevy::window::WindowCloseRequested;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+
+use super::game::{AppState, Campaign, Difficulty, Game};
+use super::map::{Island, MapSeed, RegenerateMap, RestoredIsland, SightRadius, TileGrid};
+use super::settings::data_file_path;
+use super::util::{write_data_file, HexCoord};
+
+static SAVE_FILE: &str = "save.ron";
+
+/// Version of the save file layout. A save from any other version is refused on load, so an island
+/// saved by an older build starts over rather than being resumed wrongly.
+const SAVE_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Missing,
+    Unreadable(String),
+    Version(u32),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Missing => write!(f, "There is no saved island to resume."),
+            LoadError::Unreadable(err) => write!(f, "The saved island is damaged: {}", err),
+            LoadError::Version(version) => write!(
+                f,
+                "The saved island is from a different version of the game \
+                (save format {}, this version reads {}).",
+                version, SAVE_VERSION
+            ),
+        }
+    }
+}
+
+/// Read first, so that a save from another version is rejected before the rest of it is parsed.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+/// An island in progress. The whole tile grid is kept rather than just the seed, so that a save
+/// still loads the same island if map generation changes.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    version: u32,
+    seed: u64,
+    difficulty: Difficulty,
+    campaign_levels: u32,
+    level: u32,
+    grid: TileGrid,
+    revealed: Vec<HexCoord>,
+    player: HexCoord,
+    exit: HexCoord,
+    exit_distance: u32,
+    moves: u32,
+    time: f32,
+    total_moves: u32,
+    total_time: f32,
+}
+
+impl SaveGame {
+    fn new(
+        seed: &MapSeed,
+        difficulty: Difficulty,
+        campaign: &Campaign,
+        game: &Game,
+        island: &Island,
+    ) -> Self {
+        let mut revealed: Vec<HexCoord> = island.revealed().iter().copied().collect();
+        revealed.sort();
+
+        SaveGame {
+            version: SAVE_VERSION,
+            seed: seed.0,
+            difficulty,
+            campaign_levels: campaign.levels,
+            level: game.level,
+            grid: island.grid().clone(),
+            revealed,
+            player: island.player(),
+            exit: island.exit(),
+            exit_distance: island.exit_distance(),
+            moves: game.moves,
+            time: game.time,
+            total_moves: game.total_moves,
+            total_time: game.total_time,
+        }
+    }
+
+    pub fn exists() -> bool {
+        data_file_path(SAVE_FILE).map_or(false, |path| path.exists())
+    }
+
+    pub fn load() -> Result<SaveGame, LoadError> {
+        let path = data_file_path(SAVE_FILE).ok_or(LoadError::Missing)?;
+        let contents = fs::read_to_string(path).map_err(|_| LoadError::Missing)?;
+        Self::from_ron(&contents)
+    }
+
+    fn write(&self) {
+        let path = match data_file_path(SAVE_FILE) {
+            Some(path) => path,
+            None => return,
+        };
+
+        write_data_file(&path, &self.to_ron(), "the island");
+    }
+
+    fn delete() {
+        if let Some(path) = data_file_path(SAVE_FILE) {
+            if path.exists() {
+                if let Err(err) = fs::remove_file(&path) {
+                    warn!("Could not remove the save file {}: {}", path.display(), err);
+                }
+            }
+        }
+    }
+
+    fn to_ron(&self) -> String {
+        ron::ser::to_string_pretty(self, Default::default())
+            .expect("Saves should always serialize.")
+    }
+
+    fn from_ron(contents: &str) -> Result<SaveGame, LoadError> {
+        let header: SaveHeader =
+            ron::de::from_str(contents).map_err(|err| LoadError::Unreadable(err.to_string()))?;
+        if header.version != SAVE_VERSION {
+            return Err(LoadError::Version(header.version));
+        }
+
+        let save: SaveGame =
+            ron::de::from_str(contents).map_err(|err| LoadError::Unreadable(err.to_string()))?;
+        if !save.grid.is_complete() {
+            return Err(LoadError::Unreadable(String::from(
+                "the tiles don't match the map size",
+            )));
+        }
+        if save.grid.position(save.player).is_none() || save.grid.position(save.exit).is_none() {
+            return Err(LoadError::Unreadable(String::from(
+                "the player or the exit is off the map",
+            )));
+        }
+
+        Ok(save)
+    }
+}
+
+/// Asks for a loaded save to replace the current island.
+pub struct ResumeGame(pub SaveGame);
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ResumeGame>()
+            .add_system(save_on_close)
+            .add_system(resume_game)
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(save_on_pause))
+            .add_system_set(SystemSet::on_enter(AppState::Won).with_system(delete_save));
+    }
+}
+
+/// Saves the island as soon as the game is paused.
+fn save_on_pause(
+    seed: Res<MapSeed>,
+    difficulty: Res<Difficulty>,
+    campaign: Res<Campaign>,
+    game: Res<Game>,
+    island: Option<Res<Island>>,
+) {
+    if let Some(island) = island {
+        SaveGame::new(&seed, *difficulty, &campaign, &game, &island).write();
+    }
+}
+
+/// Saves the island when the window is closed in the middle of a run.
+fn save_on_close(
+    state: Res<State<AppState>>,
+    mut close_events: EventReader<WindowCloseRequested>,
+    seed: Res<MapSeed>,
+    difficulty: Res<Difficulty>,
+    campaign: Res<Campaign>,
+    game: Res<Game>,
+    island: Option<Res<Island>>,
+) {
+    let in_run = matches!(state.current(), AppState::Playing | AppState::Paused);
+    if close_events.iter().count() == 0 || !in_run {
+        return;
+    }
+
+    if let Some(island) = island {
+        SaveGame::new(&seed, *difficulty, &campaign, &game, &island).write();
+    }
+}
+
+fn resume_game(
+    mut commands: Commands,
+    mut resume_events: EventReader<ResumeGame>,
+    mut seed: ResMut<MapSeed>,
+    mut difficulty: ResMut<Difficulty>,
+    mut campaign: ResMut<Campaign>,
+    mut game: ResMut<Game>,
+    sight_radius: Res<SightRadius>,
+    mut regenerate_events: EventWriter<RegenerateMap>,
+) {
+    for ResumeGame(save) in resume_events.iter() {
+        seed.0 = save.seed;
+        *difficulty = save.difficulty;
+        campaign.levels = save.campaign_levels;
+        *game = Game {
+            level: save.level,
+            total_moves: save.total_moves,
+            total_time: save.total_time,
+            exit_distance: save.exit_distance,
+            ..Default::default()
+        };
+
+        let mut island = Island::new(
+            save.grid.clone(),
+            save.player,
+            save.exit,
+            save.exit_distance,
+            sight_radius.0,
+        );
+        island.reveal(save.revealed.iter().copied());
+
+        commands.insert_resource(RestoredIsland {
+            island,
+            moves: save.moves,
+            time: save.time,
+        });
+        regenerate_events.send(RegenerateMap::Restore);
+    }
+}
+
+/// A finished run has nothing left to resume.
+fn delete_save() {
+    SaveGame::delete();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+
+    fn save_game() -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            seed: 83721,
+            difficulty: Difficulty::Hard,
+            campaign_levels: 1,
+            level: 1,
+            grid: TileGrid::new(
+                2,
+                2,
+                vec![
+                    TileType::Grass,
+                    TileType::DeepWater,
+                    TileType::Forest,
+                    TileType::Grass,
+                ],
+            ),
+            revealed: vec![HexCoord::new(0, 0), HexCoord::new(1, 0)],
+            player: HexCoord::new(0, 0),
+            exit: HexCoord::from_offset(1, 1),
+            exit_distance: 2,
+            moves: 5,
+            time: 12.5,
+            total_moves: 5,
+            total_time: 12.5,
+        }
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let save = save_game();
+        let loaded = SaveGame::from_ron(&save.to_ron()).unwrap();
+
+        assert_eq!(loaded.seed, save.seed);
+        assert_eq!(loaded.difficulty, save.difficulty);
+        assert_eq!(loaded.revealed, save.revealed);
+        assert_eq!(loaded.player, save.player);
+        assert_eq!(loaded.exit, save.exit);
+        assert_eq!(loaded.moves, save.moves);
+        assert_eq!(loaded.grid.get(0, 1), TileType::Forest);
+    }
+
+    #[test]
+    fn test_save_rejects_other_versions() {
+        let contents =
+            save_game()
+                .to_ron()
+                .replacen(&format!("version: {}", SAVE_VERSION), "version: 0", 1);
+        assert!(matches!(
+            SaveGame::from_ron(&contents),
+            Err(LoadError::Version(0))
+        ));
+
+        // Older saves are rejected by version even if the rest of the format changed.
+        assert!(matches!(
+            SaveGame::from_ron("(version: 0, map: [1, 2, 3])"),
+            Err(LoadError::Version(0))
+        ));
+    }
+
+    #[test]
+    fn test_save_rejects_damaged_files() {
+        assert!(matches!(
+            SaveGame::from_ron(&format!("(version: {}, seed: ", SAVE_VERSION)),
+            Err(LoadError::Unreadable(_))
+        ));
+
+        let mut save = save_game();
+        save.player = HexCoord::new(5, 5);
+        assert!(matches!(
+            SaveGame::from_ron(&save.to_ron()),
+            Err(LoadError::Unreadable(_))
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use super::game::{AppState, Campaign, Difficulty, Game};
+use super::map::{MapConfig, MapSeed, SightRadius};
+use super::settings::data_file_path;
+use super::util::write_data_file;
+
+static SCORES_FILE: &str = "scores.ron";
+
+/// Version of the high score table's layout. Tables written with another version are dropped and a
+/// new one is started, since old entries can't be matched to islands reliably.
+const SCORES_VERSION: u32 = 2;
+
+/// The best results on one island. The fewest moves and the fastest time may come from different
+/// runs.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub moves: u32,
+    pub time: f32,
+}
+
+impl HighScore {
+    /// The best of an existing high score, if any, and a new result.
+    pub fn best_of(previous: Option<HighScore>, moves: u32, time: f32) -> HighScore {
+        match previous {
+            Some(previous) => HighScore {
+                moves: previous.moves.min(moves),
+                time: previous.time.min(time),
+            },
+            None => HighScore { moves, time },
+        }
+    }
+}
+
+/// Everything that decides which island is played and how hard it is, so that scores are only
+/// compared between runs on the same island.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreKey {
+    pub seed: u64,
+    pub difficulty: Difficulty,
+    pub map: MapConfig,
+    pub sight_radius: u32,
+}
+
+impl ScoreKey {
+    pub fn new(
+        seed: u64,
+        difficulty: Difficulty,
+        map_config: &MapConfig,
+        sight_radius: &SightRadius,
+    ) -> Self {
+        ScoreKey {
+            seed,
+            difficulty,
+            map: map_config.clone(),
+            sight_radius: sight_radius.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScoresFile {
+    version: u32,
+    scores: Vec<ScoreEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ScoreEntry {
+    key: ScoreKey,
+    best: HighScore,
+}
+
+/// Best results per island, kept in the platform data directory. Map configurations can't be
+/// hashed, so entries are found by searching the list.
+#[derive(Default)]
+pub struct HighScores {
+    path: Option<PathBuf>,
+    scores: Vec<ScoreEntry>,
+}
+
+impl HighScores {
+    pub fn load() -> Self {
+        let path = data_file_path(SCORES_FILE);
+        let scores = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(contents) => Self::from_ron(&contents).unwrap_or_else(|err| {
+                    warn!(
+                        "Starting a new high score table, {} is unusable: {}",
+                        path.display(),
+                        err
+                    );
+                    Vec::new()
+                }),
+                Err(_) => Vec::new(),
+            },
+            None => {
+                warn!("No data directory found, high scores will not be saved");
+                Vec::new()
+            }
+        };
+
+        HighScores { path, scores }
+    }
+
+    pub fn get(&self, key: &ScoreKey) -> Option<HighScore> {
+        self.scores
+            .iter()
+            .find(|entry| entry.key == *key)
+            .map(|entry| entry.best)
+    }
+
+    pub fn record(&mut self, key: ScoreKey, moves: u32, time: f32) {
+        let best = HighScore::best_of(self.get(&key), moves, time);
+        match self.scores.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => entry.best = best,
+            None => self.scores.push(ScoreEntry { key, best }),
+        }
+    }
+
+    pub fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        write_data_file(path, &self.to_ron(), "high scores");
+    }
+
+    fn to_ron(&self) -> String {
+        let file = ScoresFile {
+            version: SCORES_VERSION,
+            scores: self.scores.clone(),
+        };
+        ron::ser::to_string_pretty(&file, Default::default())
+            .expect("High scores should always serialize.")
+    }
+
+    fn from_ron(contents: &str) -> Result<Vec<ScoreEntry>, String> {
+        let file: ScoresFile = ron::de::from_str(contents).map_err(|err| err.to_string())?;
+        if file.version != SCORES_VERSION {
+            return Err(format!(
+                "version {} is not supported, expected {}",
+                file.version, SCORES_VERSION
+            ));
+        }
+
+        Ok(file.scores)
+    }
+}
+
+pub struct ScoresPlugin;
+
+impl Plugin for ScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load())
+            .add_system_set(SystemSet::on_enter(AppState::Won).with_system(record_score));
+    }
+}
+
+/// Records the island just won. Campaign islands are left out, since their size depends on the
+/// level as well as the seed.
+fn record_score(
+    mut high_scores: ResMut<HighScores>,
+    seed: Res<MapSeed>,
+    difficulty: Res<Difficulty>,
+    map_config: Res<MapConfig>,
+    sight_radius: Res<SightRadius>,
+    campaign: Res<Campaign>,
+    game: Res<Game>,
+) {
+    if campaign.is_campaign() {
+        return;
+    }
+
+    let key = ScoreKey::new(seed.0, *difficulty, &map_config, &sight_radius);
+    high_scores.record(key, game.moves, game.time);
+    high_scores.save();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use float_cmp::*;
+
+    fn key(seed: u64, difficulty: Difficulty) -> ScoreKey {
+        ScoreKey {
+            seed,
+            difficulty,
+            map: MapConfig::default(),
+            sight_radius: 3,
+        }
+    }
+
+    #[test]
+    fn test_record_keeps_best_moves_and_time() {
+        let mut high_scores = HighScores::default();
+        high_scores.record(key(83721, Difficulty::Hard), 40, 90.0);
+        high_scores.record(key(83721, Difficulty::Hard), 30, 120.0);
+        high_scores.record(key(83721, Difficulty::Easy), 10, 20.0);
+
+        let best = high_scores.get(&key(83721, Difficulty::Hard)).unwrap();
+        assert_eq!(best.moves, 30);
+        assert!(approx_eq!(f32, best.time, 90.0));
+        assert_eq!(high_scores.get(&key(83721, Difficulty::Normal)), None);
+    }
+
+    #[test]
+    fn test_record_keeps_map_configs_apart() {
+        let mut high_scores = HighScores::default();
+        let default = key(5, Difficulty::Normal);
+        let small = ScoreKey {
+            map: MapConfig {
+                width: 20,
+                ..MapConfig::default()
+            },
+            ..default.clone()
+        };
+        let far_sighted = ScoreKey {
+            sight_radius: 6,
+            ..default.clone()
+        };
+        high_scores.record(default.clone(), 40, 90.0);
+        high_scores.record(small.clone(), 10, 20.0);
+
+        assert_eq!(high_scores.get(&default).unwrap().moves, 40);
+        assert_eq!(high_scores.get(&small).unwrap().moves, 10);
+        assert_eq!(high_scores.get(&far_sighted), None);
+    }
+
+    #[test]
+    fn test_scores_round_trip() {
+        let mut high_scores = HighScores::default();
+        high_scores.record(key(1, Difficulty::Easy), 12, 30.5);
+        high_scores.record(key(2, Difficulty::Hard), 50, 100.0);
+
+        let scores = HighScores::from_ron(&high_scores.to_ron()).unwrap();
+        assert_eq!(scores, high_scores.scores);
+    }
+
+    #[test]
+    fn test_scores_reject_other_versions() {
+        let contents = "(version: 1, scores: [])";
+        assert!(HighScores::from_ron(contents).is_err());
+    }
+
+    #[test]
+    fn test_scores_reject_corrupt_file() {
+        assert!(HighScores::from_ron("(version: 2, scores: [(key: ").is_err());
+        assert!(HighScores::from_ron("").is_err());
+    }
+}
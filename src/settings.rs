@@ -0,0 +1,401 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::export::ExportOptions;
+use super::game::Difficulty;
+use super::map::MapConfig;
+use super::util::HexDirection;
+
+static SETTINGS_PATH: &str = "settings.ron";
+
+/// Path of a file the game keeps in the platform data directory, if there is one.
+pub fn data_file_path(name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("i_want_to_go_home").join(name))
+}
+
+/// User settings, read from `settings.ron` in the working directory and then overridden by any
+/// command line flags.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub seed: Option<u64>,
+    pub difficulty: Difficulty,
+    pub sight_radius: u32,
+    /// Number of islands in a run; more than one plays a campaign.
+    pub campaign_levels: u32,
+    pub map: MapConfig,
+    /// Runs without a window, playing `moves` and printing the outcome.
+    #[serde(skip)]
+    pub headless: bool,
+    /// Steps taken one per frame in headless mode.
+    #[serde(skip)]
+    pub moves: Vec<HexDirection>,
+    /// Prints the island as text when the run ends, instead of opening a window.
+    #[serde(skip)]
+    pub dump_map: bool,
+    /// Writes a picture of the island to this file instead of opening a window.
+    #[serde(skip)]
+    pub export_png: Option<PathBuf>,
+    #[serde(skip)]
+    pub export: ExportOptions,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            difficulty: Difficulty::default(),
+            sight_radius: 3,
+            campaign_levels: 1,
+            map: MapConfig::default(),
+            headless: false,
+            moves: Vec::new(),
+            dump_map: false,
+            export_png: None,
+            export: ExportOptions::default(),
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load());
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let mut settings = Self::from_file(SETTINGS_PATH);
+        settings.apply_args(std::env::args().skip(1));
+        settings
+    }
+
+    fn from_file(path: &str) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Self::default(),
+        };
+
+        match ron::de::from_str(&contents) {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!("Ignoring invalid settings file {}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    /// Reads a map configuration on its own, in the same format as the `map` section of the
+    /// settings file.
+    fn map_config_from_file(path: &str) -> Option<MapConfig> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Could not read map configuration file {}: {}", path, err);
+                return None;
+            }
+        };
+
+        match ron::de::from_str(&contents) {
+            Ok(map) => Some(map),
+            Err(err) => {
+                warn!("Ignoring invalid map configuration file {}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    /// Whether the game runs in the terminal rather than in a window.
+    pub fn windowless(&self) -> bool {
+        self.headless || self.dump_map || self.export_png.is_some()
+    }
+
+    fn apply_args(&mut self, mut args: impl Iterator<Item = String>) {
+        while let Some(arg) = args.next() {
+            let args = &mut args;
+            let flag = arg.as_str();
+            match flag {
+                "--seed" => {
+                    if let Some(seed) = next_value(args, flag, "a non-negative integer") {
+                        self.seed = Some(seed);
+                    }
+                }
+                "--difficulty" => parse_next(
+                    args,
+                    flag,
+                    "one of easy, normal or hard",
+                    &mut self.difficulty,
+                ),
+                "--sight-radius" => {
+                    parse_next(args, flag, "a non-negative integer", &mut self.sight_radius)
+                }
+                "--campaign" => {
+                    parse_next(args, flag, "a number of islands", &mut self.campaign_levels)
+                }
+                "--map-config" => {
+                    let path: Option<String> =
+                        next_value(args, flag, "the path of a map configuration file");
+                    if let Some(map) = path.and_then(|path| Self::map_config_from_file(&path)) {
+                        self.map = map;
+                    }
+                }
+                "--width" => parse_next(args, flag, "a number of tiles", &mut self.map.width),
+                "--height" => parse_next(args, flag, "a number of tiles", &mut self.map.height),
+                "--water-level" => {
+                    parse_next(args, flag, BETWEEN_0_AND_1, &mut self.map.water_level)
+                }
+                "--forest-level" => {
+                    parse_next(args, flag, BETWEEN_0_AND_1, &mut self.map.forest_level)
+                }
+                "--shallows-depth" => {
+                    parse_next(args, flag, BETWEEN_0_AND_1, &mut self.map.shallows_depth)
+                }
+                "--beach-width" => {
+                    parse_next(args, flag, BETWEEN_0_AND_1, &mut self.map.beach_width)
+                }
+                "--mountain-level" => {
+                    parse_next(args, flag, BETWEEN_0_AND_1, &mut self.map.mountain_level)
+                }
+                "--walkable-threshold" => parse_next(
+                    args,
+                    flag,
+                    "a number of tiles",
+                    &mut self.map.walkable_tile_threshold,
+                ),
+                "--octave-scale" => {
+                    parse_next(args, flag, "a positive number", &mut self.map.octave_scale)
+                }
+                "--octave-count" => {
+                    parse_next(args, flag, "a positive integer", &mut self.map.octave_count)
+                }
+                "--octave-persistence" => parse_next(
+                    args,
+                    flag,
+                    "a positive number",
+                    &mut self.map.octave_persistence,
+                ),
+                "--moisture-scale" => parse_next(
+                    args,
+                    flag,
+                    "a positive number",
+                    &mut self.map.moisture_scale,
+                ),
+                "--rivers" => {
+                    parse_next(args, flag, "a number of rivers", &mut self.map.river_count)
+                }
+                "--river-source-level" => parse_next(
+                    args,
+                    flag,
+                    BETWEEN_0_AND_1,
+                    &mut self.map.river_source_level,
+                ),
+                "--ford-spacing" => parse_next(
+                    args,
+                    flag,
+                    "a non-negative integer",
+                    &mut self.map.ford_spacing,
+                ),
+                "--shape" => parse_next(
+                    args,
+                    flag,
+                    "one of radial, hexagonal, archipelago, archipelago:<islands>, atoll or \
+                    crescent",
+                    &mut self.map.shape,
+                ),
+                "--headless" => self.headless = true,
+                "--moves" => parse_list(
+                    args,
+                    flag,
+                    "comma-separated directions such as n,ne,se",
+                    &mut self.moves,
+                ),
+                "--dump-map" => self.dump_map = true,
+                "--export-png" => {
+                    if let Some(path) = next_value(args, flag, "a file name") {
+                        self.export_png = Some(path);
+                    }
+                }
+                "--scale" => {
+                    if let Some(scale) = next_value::<NonZeroU32>(args, flag, "a positive integer")
+                    {
+                        self.export.scale = scale.get();
+                    }
+                }
+                "--flat" => self.export.flat = true,
+                "--overlays" => parse_list(
+                    args,
+                    flag,
+                    "a comma-separated list such as spawn,exit,path",
+                    &mut self.export.overlays,
+                ),
+                _ => warn!("Ignoring unknown argument {}", arg),
+            }
+        }
+    }
+}
+
+const BETWEEN_0_AND_1: &str = "a number between 0 and 1";
+
+/// Parses the value following `flag`, warning that the flag expects `expected` if it is missing or
+/// invalid.
+fn next_value<T: FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    expected: &str,
+) -> Option<T> {
+    match args.next().map(|value| value.parse()) {
+        Some(Ok(value)) => Some(value),
+        _ => {
+            warn!("{} expects {}", flag, expected);
+            None
+        }
+    }
+}
+
+/// Parses the value following `flag` into `field`, which is left alone if the value is invalid.
+fn parse_next<T: FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    expected: &str,
+    field: &mut T,
+) {
+    if let Some(value) = next_value(args, flag, expected) {
+        *field = value;
+    }
+}
+
+/// Parses the comma-separated list following `flag` into `field`, skipping invalid entries.
+fn parse_list<T: FromStr<Err = String>>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    expected: &str,
+    field: &mut Vec<T>,
+) {
+    let list: String = match next_value(args, flag, expected) {
+        Some(list) => list,
+        None => return,
+    };
+    *field = list
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| match entry.parse() {
+            Ok(value) => Some(value),
+            Err(err) => {
+                warn!("Ignoring part of {}: {}", flag, err);
+                None
+            }
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Overlay;
+    use float_cmp::*;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_args_override_map_config() {
+        let mut settings = Settings::default();
+        settings.apply_args(args(&[
+            "--width",
+            "60",
+            "--water-level",
+            "0.3",
+            "--octave-count",
+            "4",
+            "--height",
+            "oops",
+        ]));
+
+        assert_eq!(settings.map.width, 60);
+        assert_eq!(settings.map.height, MapConfig::default().height);
+        assert!(approx_eq!(f64, settings.map.water_level, 0.3));
+        assert_eq!(settings.map.octave_count, 4);
+    }
+
+    #[test]
+    fn test_args_headless_moves() {
+        let mut settings = Settings::default();
+        settings.apply_args(args(&["--headless", "--moves", "n, ne,up,,SW"]));
+
+        assert!(settings.headless);
+        assert_eq!(
+            settings.moves,
+            vec![
+                HexDirection::North,
+                HexDirection::NorthEast,
+                HexDirection::SouthWest
+            ]
+        );
+        assert!(!settings.dump_map);
+        assert!(settings.windowless());
+    }
+
+    #[test]
+    fn test_args_dump_map() {
+        let mut settings = Settings::default();
+        assert!(!settings.windowless());
+
+        settings.apply_args(args(&["--dump-map"]));
+
+        assert!(settings.dump_map);
+        assert!(!settings.headless);
+        assert!(settings.windowless());
+    }
+
+    #[test]
+    fn test_args_export_png() {
+        let mut settings = Settings::default();
+        settings.apply_args(args(&[
+            "--export-png",
+            "island.png",
+            "--scale",
+            "0",
+            "--overlays",
+            "path,player, Spawn",
+        ]));
+
+        assert_eq!(settings.export_png, Some(PathBuf::from("island.png")));
+        assert!(settings.windowless());
+        assert_eq!(
+            settings.export,
+            ExportOptions {
+                scale: 1,
+                flat: false,
+                overlays: vec![Overlay::Path, Overlay::Spawn],
+            }
+        );
+
+        settings.apply_args(args(&["--scale", "4", "--flat"]));
+        assert_eq!(settings.export.scale, 4);
+        assert!(settings.export.flat);
+    }
+
+    #[test]
+    fn test_map_section_fills_in_defaults() {
+        let settings: Settings =
+            ron::de::from_str("(map: (width: 50, forest_level: 0.6))").unwrap();
+
+        assert_eq!(settings.map.width, 50);
+        assert!(approx_eq!(f64, settings.map.forest_level, 0.6));
+        assert_eq!(
+            settings.map.walkable_tile_threshold,
+            MapConfig::default().walkable_tile_threshold
+        );
+    }
+}
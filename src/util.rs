@@ -1,114 +1,48 @@
 use bevy::prelude::*;
+use std::fs;
+use std::path::Path;
 
-static TILE_SIZE: i32 = 16;
+pub use i_want_to_go_home::core::hex::*;
 
-#[derive(Copy, Clone)]
-pub struct Hex {
-    q: f32,
-    r: f32,
-}
-
-impl Hex {
-    pub fn new(q: f32, r: f32) -> Self {
-        Self { q, r }
-    }
-
-    pub fn from_pixel_coords(coords: &Vec2) -> Self {
-        let root3 = 3.0_f32.sqrt();
-        let q = (2.0 / 3.0 * coords.x) / Self::get_size_w();
-        let r = (coords.y / Self::get_size_h() - root3 / 2. * q) / root3;
-
-        Self::new(q, r).rounded()
-    }
-
-    pub fn to_cube_coords(&self) -> Cube {
-        let x = self.q;
-        let z = self.r;
-        let y = -x - z;
-
-        Cube::new(x, y, z)
-    }
-
-    pub fn to_pixel_coords(&self) -> Vec2 {
-        let root3 = 3.0_f32.sqrt();
-        let translate_x = Self::get_size_w() * (3.0 / 2.0 * self.q);
-        let translate_y = Self::get_size_h() * (root3 / 2.0 * self.q + root3 * self.r);
-        return Vec2::new(translate_x, translate_y);
-    }
+pub static TILE_SIZE: i32 = 16;
 
-    pub fn rounded(&self) -> Hex {
-        self.to_cube_coords().rounded().to_axial_coords()
-    }
-
-    pub fn distance_to(&self, other: &Hex) -> u32 {
-        let ac = self.to_cube_coords();
-        let bc = other.to_cube_coords();
-
-        ac.distance_to(&bc)
-    }
-
-    fn get_size_w() -> f32 {
-        TILE_SIZE as f32 / 2.0
-    }
+/// Conversions between tiles and the positions their sprites are drawn at.
+pub trait PixelCoords {
+    fn from_pixel_coords(coords: &Vec2) -> Self;
 
-    fn get_size_h() -> f32 {
-        TILE_SIZE as f32 / 3.0_f32.sqrt()
-    }
+    fn to_pixel_coords(&self) -> Vec2;
 }
 
-impl PartialEq for Hex {
-    fn eq(&self, other: &Self) -> bool {
-        let ar = self.r as i32;
-        let aq = self.q as i32;
-        let br = other.r as i32;
-        let bq = other.q as i32;
-
-        ar == br && aq == bq
+impl PixelCoords for Hex {
+    fn from_pixel_coords(coords: &Vec2) -> Self {
+        Hex::from_point(coords.x, coords.y, TILE_SIZE as f32).rounded()
     }
-}
 
-impl Eq for Hex  {}
-
-pub struct Cube {
-    x: f32,
-    y: f32,
-    z: f32,
-}
-
-impl Cube {
-    pub fn new(x: f32, y: f32, z: f32) -> Self {
-        Self { x, y, z }
+    fn to_pixel_coords(&self) -> Vec2 {
+        let (x, y) = self.to_point(TILE_SIZE as f32);
+        Vec2::new(x, y)
     }
+}
 
-    pub fn to_axial_coords(&self) -> Hex {
-        let q = self.x;
-        let r = self.z;
-
-        Hex::new(q, r)
+impl PixelCoords for HexCoord {
+    fn from_pixel_coords(coords: &Vec2) -> Self {
+        Hex::from_point(coords.x, coords.y, TILE_SIZE as f32).to_hex_coord()
     }
 
-    pub fn rounded(&self) -> Cube {
-        let mut rx = self.x.round();
-        let mut ry = self.y.round();
-        let mut rz = self.z.round();
-
-        let x_diff = (rx - self.x).abs();
-        let y_diff = (ry - self.y).abs();
-        let z_diff = (rz - self.z).abs();
-
-        if x_diff > y_diff && x_diff > z_diff {
-            rx = -ry - rz;
-        } else if y_diff > z_diff {
-            ry = -rx - rz;
-        } else {
-            rz = -rx - ry;
-        }
-
-        Cube::new(rx, ry, rz)
+    fn to_pixel_coords(&self) -> Vec2 {
+        Hex::from(*self).to_pixel_coords()
     }
+}
 
-    pub fn distance_to(&self, other: &Cube) -> u32 {
-        (((self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()) / 2.0) as u32
+/// Writes `contents` to `path`, creating its directory if needed. Failures are only logged, since
+/// the game can carry on without its files.
+pub fn write_data_file(path: &Path, contents: &str, what: &str) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, contents));
+    if let Err(err) = result {
+        warn!("Could not save {} to {}: {}", what, path.display(), err);
     }
 }
 
@@ -124,4 +58,56 @@ mod tests {
         let converted_pixel_coords = hex_coords.to_pixel_coords();
         assert!(approx_eq!(f32, pixel_coords.x, converted_pixel_coords.x));
     }
+
+    #[test]
+    fn test_hex_coord_pixel_round_trip() {
+        for q in -5..=5 {
+            for r in -5..=5 {
+                let coord = HexCoord::new(q, r);
+                assert_eq!(HexCoord::from_pixel_coords(&coord.to_pixel_coords()), coord);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_coord_from_off_center_pixel_coords() {
+        for q in -5..=5 {
+            for r in -5..=5 {
+                let coord = HexCoord::new(q, r);
+                let center = coord.to_pixel_coords();
+                for offset in [Vec2::new(3., 2.), Vec2::new(-3., -2.), Vec2::new(-2., 5.)].iter() {
+                    let off_center = center + *offset;
+                    assert_eq!(HexCoord::from_pixel_coords(&off_center), coord);
+                }
+            }
+        }
+        assert_eq!(
+            HexCoord::from_pixel_coords(&Vec2::new(-13., -3.)),
+            HexCoord::new(-1, 0)
+        );
+    }
+
+    #[test]
+    fn test_hex_direction_matches_pixel_coords() {
+        let origin = HexCoord::new(0, 0).to_pixel_coords();
+        let north = HexCoord::new(0, 0)
+            .neighbor(HexDirection::North)
+            .to_pixel_coords();
+        let south_east = HexCoord::new(0, 0)
+            .neighbor(HexDirection::SouthEast)
+            .to_pixel_coords();
+
+        assert!(approx_eq!(f32, north.x, origin.x));
+        assert!(north.y > origin.y);
+        assert!(south_east.x > origin.x);
+        assert!(south_east.y < origin.y);
+    }
+
+    #[test]
+    fn test_hex_direction_nearest_pixel_offset() {
+        for direction in HexDirection::ALL.iter() {
+            let offset = direction.offset().to_pixel_coords();
+            assert_eq!(HexDirection::nearest(offset.x, offset.y), *direction);
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use i_want_to_go_home::core::hex::{find_path, HexCoord};
+use i_want_to_go_home::core::map::{generate_island, Difficulty, MapConfig};
+use i_want_to_go_home::core::Island;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Deserialize;
+use std::env;
+use std::process::Command;
+
+const SEED: u64 = 83721;
+
+#[derive(Debug, PartialEq, Deserialize)]
+enum Outcome {
+    Won,
+    Stranded,
+}
+
+#[derive(Debug, Deserialize)]
+struct Report {
+    outcome: Outcome,
+    seed: u64,
+    moves: u32,
+    player: HexCoord,
+    exit: HexCoord,
+}
+
+/// The island the game generates for `SEED` with the default settings.
+fn island() -> Island {
+    let config = MapConfig::default().for_level(1);
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let generated = generate_island(&config, &mut rng, |_| {}).unwrap();
+    Island::place(generated, Difficulty::default(), 3, &mut rng)
+}
+
+/// Runs the game headless with `moves`, away from any settings file in the working directory.
+fn run_headless(moves: &str) -> Report {
+    let output = Command::new(env!("CARGO_BIN_EXE_i_want_to_go_home"))
+        .args(["--headless", "--seed", &SEED.to_string(), "--moves", moves])
+        .current_dir(env::temp_dir())
+        .output()
+        .expect("The game should start.");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Log lines may be printed around the report.
+    let start = stdout
+        .find("(\n    outcome:")
+        .expect("The game should print a report.");
+    let report = &stdout[start..];
+    let end = report.find("\n)").expect("The report should be complete.") + 2;
+    ron::de::from_str(&report[..end]).expect("The report should be valid RON.")
+}
+
+#[test]
+fn test_headless_walks_home() {
+    let island = island();
+    let path = find_path(island.player(), island.exit(), |hex| {
+        island.movement_cost(hex)
+    })
+    .expect("The exit should be reachable from the spawn.");
+    let moves: Vec<String> = path
+        .windows(2)
+        .map(|step| step[0].direction_to(&step[1]).unwrap().to_string())
+        .collect();
+
+    let report = run_headless(&moves.join(","));
+
+    assert_eq!(report.outcome, Outcome::Won);
+    assert_eq!(report.seed, SEED);
+    assert_eq!(report.moves as usize, moves.len());
+    assert_eq!(report.exit, island.exit());
+    assert_eq!(report.player, island.exit());
+}
+
+#[test]
+fn test_headless_stranded_when_moves_run_out() {
+    let island = island();
+
+    let report = run_headless("");
+
+    assert_eq!(report.outcome, Outcome::Stranded);
+    assert_eq!(report.moves, 0);
+    assert_eq!(report.player, island.player());
+    assert_eq!(report.exit, island.exit());
+}